@@ -1,4 +1,17 @@
-use std::{collections::HashMap, ops::Add};
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+    io::{self, Write},
+    ops::Add,
+    path::Path,
+    time::Duration,
+};
+
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEventKind},
+    execute, terminal,
+};
 
 fn read_input(input: Option<&str>) -> String {
     let input = match input {
@@ -35,8 +48,106 @@ impl Add for Coord {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum Neighborhood {
+    /// 4 orthogonal neighbours.
+    VonNeumann,
+    /// 8 surrounding neighbours.
+    Moore,
+    /// 6 neighbours on an "odd-r" hexagonal layout, odd rows are shifted right.
+    Hex,
+    Custom(Vec<Coord>),
+}
+
+impl Neighborhood {
+    fn offsets(&self, coord: &Coord) -> Vec<Coord> {
+        // Clockwise
+        // 0 -----> x
+        // |
+        // |
+        // v
+        // y
+        match self {
+            Neighborhood::VonNeumann => {
+                vec![(1, 0).into(), (0, 1).into(), (-1, 0).into(), (0, -1).into()]
+            }
+            Neighborhood::Moore => vec![
+                (1, 0).into(),
+                (1, 1).into(),
+                (0, 1).into(),
+                (-1, 1).into(),
+                (-1, 0).into(),
+                (-1, -1).into(),
+                (0, -1).into(),
+                (1, -1).into(),
+            ],
+            Neighborhood::Hex if coord.y.rem_euclid(2) == 0 => vec![
+                (1, 0).into(),
+                (0, 1).into(),
+                (-1, 1).into(),
+                (-1, 0).into(),
+                (-1, -1).into(),
+                (0, -1).into(),
+            ],
+            Neighborhood::Hex => vec![
+                (1, 0).into(),
+                (1, 1).into(),
+                (0, 1).into(),
+                (-1, 0).into(),
+                (0, -1).into(),
+                (1, -1).into(),
+            ],
+            Neighborhood::Custom(offsets) => offsets.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+/// Decide whether a roll can be accessed by a forklift.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    neighborhood: Neighborhood,
+    toroidal: bool,
+    threshold: usize,
+    comparison: Comparison,
+    /// Characters that count as an occupied cell.
+    occupied: String,
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Rule {
+            neighborhood: Neighborhood::Moore,
+            toroidal: false,
+            threshold: 4,
+            comparison: Comparison::Less,
+            occupied: "@".to_string(),
+        }
+    }
+}
+
+impl Rule {
+    fn accessible(&self, neighbors: usize) -> bool {
+        match self.comparison {
+            Comparison::Less => neighbors < self.threshold,
+            Comparison::LessOrEqual => neighbors <= self.threshold,
+            Comparison::Equal => neighbors == self.threshold,
+            Comparison::GreaterOrEqual => neighbors >= self.threshold,
+            Comparison::Greater => neighbors > self.threshold,
+        }
+    }
+}
+
 #[allow(unused)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Grid {
     data: HashMap<Coord, char>,
     rows: usize,
@@ -55,31 +166,28 @@ impl Grid {
         self.data.get(coord)
     }
 
-    fn is_roll(&self, coord: &Coord) -> bool {
-        self.in_grid(coord).is_some_and(|c| "@".contains(*c))
+    fn is_roll(&self, coord: &Coord, rule: &Rule) -> bool {
+        self.in_grid(coord)
+            .is_some_and(|c| rule.occupied.contains(*c))
     }
 
-    fn neighbors(&self, coord: &Coord) -> Vec<Coord> {
+    /// Wrap a coordinate around the grid edges (toroidal topology).
+    fn wrap(&self, coord: Coord) -> Coord {
+        Coord::new(
+            coord.x.rem_euclid(self.cols as isize),
+            coord.y.rem_euclid(self.rows as isize),
+        )
+    }
+
+    fn neighbors(&self, coord: &Coord, rule: &Rule) -> Vec<Coord> {
         let mut neighbors = Vec::new();
-        // Clockwise
-        // 0 -----> x
-        // |
-        // |
-        // v
-        // y
-        let directions: [Coord; 8] = [
-            (1, 0).into(),
-            (1, 1).into(),
-            (0, 1).into(),
-            (-1, 1).into(),
-            (-1, 0).into(),
-            (-1, -1).into(),
-            (0, -1).into(),
-            (1, -1).into(),
-        ];
-        for direction in directions.iter() {
-            let neighbor = *coord + *direction;
-            if self.is_roll(&neighbor) {
+        for direction in rule.neighborhood.offsets(coord).iter() {
+            let mut neighbor = *coord + *direction;
+            if rule.toroidal {
+                neighbor = self.wrap(neighbor);
+            }
+            if neighbor != *coord && self.is_roll(&neighbor, rule) && !neighbors.contains(&neighbor)
+            {
                 neighbors.push(neighbor);
             }
         }
@@ -137,18 +245,263 @@ fn from_vec_of_vec(value: Vec<Vec<char>>) -> Grid {
     }
 }
 
-fn run(input: String) -> usize {
-    let mut result = 0;
-    let mut grid = Grid::from(input.as_str());
-    grid.print();
+/// Character to colour mapping used by the image exports.
+#[derive(Debug, Clone)]
+pub struct Palette {
+    colors: Vec<(char, [u8; 3])>,
+    /// Colour used for characters missing from the palette.
+    fallback: [u8; 3],
+}
+
+impl Palette {
+    pub fn new(colors: &[(char, [u8; 3])], fallback: [u8; 3]) -> Palette {
+        Palette {
+            colors: colors.to_vec(),
+            fallback,
+        }
+    }
+
+    /// Index of a character in the palette, the fallback colour comes last.
+    fn index(&self, c: char) -> usize {
+        self.colors
+            .iter()
+            .position(|(key, _)| *key == c)
+            .unwrap_or(self.colors.len())
+    }
+
+    fn color(&self, c: char) -> [u8; 3] {
+        self.colors
+            .get(self.index(c))
+            .map_or(self.fallback, |(_, color)| *color)
+    }
+
+    /// Flat `[r, g, b, ...]` table, as expected by the GIF encoder.
+    fn rgb_table(&self) -> Vec<u8> {
+        self.colors
+            .iter()
+            .map(|(_, color)| *color)
+            .chain(std::iter::once(self.fallback))
+            .flatten()
+            .collect()
+    }
+}
+
+impl Grid {
+    /// Palette indexes, one per pixel, each cell being `scale` x `scale` pixels.
+    fn pixels(&self, palette: &Palette, scale: usize) -> Vec<usize> {
+        let mut pixels = Vec::with_capacity(self.rows * self.cols * scale * scale);
+        for y in 0..self.rows * scale {
+            for x in 0..self.cols * scale {
+                let c = self.data[&Coord::new((x / scale) as isize, (y / scale) as isize)];
+                pixels.push(palette.index(c));
+            }
+        }
+        pixels
+    }
+
+    pub fn to_png(&self, palette: &Palette, scale: usize) -> io::Result<Vec<u8>> {
+        let dimension = |cells: usize| {
+            cells
+                .checked_mul(scale)
+                .and_then(|pixels| u32::try_from(pixels).ok())
+                .ok_or_else(|| io::Error::other("Image too large for PNG"))
+        };
+        let (width, height) = (dimension(self.cols)?, dimension(self.rows)?);
+        let table = palette.rgb_table();
+        let data: Vec<u8> = self
+            .pixels(palette, scale)
+            .iter()
+            .flat_map(|i| table[i * 3..i * 3 + 3].to_vec())
+            .collect();
+
+        let mut output = Vec::new();
+        let mut encoder = png::Encoder::new(&mut output, width, height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&data)?;
+        writer.finish()?;
+        Ok(output)
+    }
+
+    pub fn to_svg(&self, palette: &Palette, scale: usize) -> io::Result<String> {
+        let dimension = |cells: usize| {
+            cells
+                .checked_mul(scale)
+                .ok_or_else(|| io::Error::other("Image too large for SVG"))
+        };
+        let (width, height) = (dimension(self.cols)?, dimension(self.rows)?);
+        let mut output = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}" shape-rendering="crispEdges">"#
+        );
+        output.push('\n');
+        for y in 0..self.rows {
+            for x in 0..self.cols {
+                let [r, g, b] = palette.color(self.data[&Coord::new(x as isize, y as isize)]);
+                output.push_str(&format!(
+                    r##"<rect x="{}" y="{}" width="{scale}" height="{scale}" fill="#{r:02x}{g:02x}{b:02x}"/>"##,
+                    x * scale,
+                    y * scale,
+                ));
+                output.push('\n');
+            }
+        }
+        output.push_str("</svg>\n");
+        Ok(output)
+    }
+}
+
+/// Stitch simulation steps into an animated GIF, `delay` is in 1/100 s.
+pub fn frames_to_gif(
+    frames: &[Grid],
+    palette: &Palette,
+    scale: usize,
+    delay: u16,
+) -> io::Result<Vec<u8>> {
+    let Some(first) = frames.first() else {
+        return Err(io::Error::other("No frame to encode"));
+    };
+    let dimension = |cells: usize| {
+        cells
+            .checked_mul(scale)
+            .and_then(|pixels| u16::try_from(pixels).ok())
+            .ok_or_else(|| io::Error::other("Image too large for GIF"))
+    };
+    let (width, height) = (dimension(first.cols)?, dimension(first.rows)?);
+    let mut output = Vec::new();
+    {
+        let mut encoder = gif::Encoder::new(&mut output, width, height, &palette.rgb_table())
+            .map_err(io::Error::other)?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(io::Error::other)?;
+        for grid in frames {
+            let buffer: Vec<u8> = grid
+                .pixels(palette, scale)
+                .iter()
+                .map(|i| {
+                    u8::try_from(*i).map_err(|_| io::Error::other("Palette too large for GIF"))
+                })
+                .collect::<io::Result<_>>()?;
+            let frame = gif::Frame {
+                width,
+                height,
+                delay,
+                buffer: buffer.into(),
+                ..Default::default()
+            };
+            encoder.write_frame(&frame).map_err(io::Error::other)?;
+        }
+    }
+    Ok(output)
+}
+
+/// Write one PNG and one SVG per step plus an animated GIF into `dir`.
+fn export_frames(dir: &Path, frames: &[Grid], palette: &Palette) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    for (step, grid) in frames.iter().enumerate() {
+        fs::write(
+            dir.join(format!("step_{step:04}.png")),
+            grid.to_png(palette, 8)?,
+        )?;
+        fs::write(
+            dir.join(format!("step_{step:04}.svg")),
+            grid.to_svg(palette, 8)?,
+        )?;
+    }
+    fs::write(
+        dir.join("steps.gif"),
+        frames_to_gif(frames, palette, 8, 20)?,
+    )?;
+    Ok(())
+}
+
+/// Outcome of the removal process, wave by wave.
+#[derive(Debug, Default)]
+pub struct RemovalHistory {
+    rows: usize,
+    cols: usize,
+    /// Number of rolls removed in each wave (wave 1 is `waves[0]`).
+    waves: Vec<usize>,
+    /// Wave (1-based) at which each roll was removed.
+    removed_at: HashMap<Coord, usize>,
+    /// Rolls that are never removable.
+    stable_core: HashSet<Coord>,
+}
+
+impl RemovalHistory {
+    pub fn total(&self) -> usize {
+        self.waves.iter().sum()
+    }
+
+    pub fn wave_of(&self, coord: &Coord) -> Option<usize> {
+        self.removed_at.get(coord).copied()
+    }
+
+    /// State of `grid` once the first `wave` waves have been removed.
+    pub fn grid_after(&self, grid: &Grid, wave: usize) -> Grid {
+        let mut snapshot = grid.clone();
+        for (coord, removed) in self.removed_at.iter() {
+            if *removed <= wave {
+                snapshot.set(coord, 'x');
+            }
+        }
+        snapshot
+    }
+
+    /// Grid of wave numbers: `Some(wave)` for removed rolls, `None` elsewhere.
+    pub fn heatmap(&self) -> Vec<Vec<Option<usize>>> {
+        (0..self.rows)
+            .map(|y| {
+                (0..self.cols)
+                    .map(|x| self.wave_of(&Coord::new(x as isize, y as isize)))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Text rendering of the heatmap, one column per cell.
+    /// Empty cells are `.` and the stable core is `@`.
+    pub fn heatmap_to_string(&self) -> String {
+        let width = self.waves.len().to_string().len();
+        let mut output = String::new();
+        for (y, row) in self.heatmap().iter().enumerate() {
+            let cells: Vec<String> = row
+                .iter()
+                .enumerate()
+                .map(|(x, wave)| match wave {
+                    Some(wave) => format!("{:>width$}", wave),
+                    None if self
+                        .stable_core
+                        .contains(&Coord::new(x as isize, y as isize)) =>
+                    {
+                        format!("{:>width$}", '@')
+                    }
+                    None => format!("{:>width$}", '.'),
+                })
+                .collect();
+            output.push_str(&cells.join(" "));
+            output.push('\n');
+        }
+        output
+    }
+}
+
+fn simulate(grid: &mut Grid, rule: &Rule) -> RemovalHistory {
+    let mut history = RemovalHistory {
+        rows: grid.rows,
+        cols: grid.cols,
+        ..Default::default()
+    };
 
     loop {
         let mut roll_removed = Vec::new();
         for y in 0..grid.rows {
             for x in 0..grid.cols {
                 let current_coord = Coord::new(x as isize, y as isize);
-                if grid.is_roll(&current_coord) && grid.neighbors(&current_coord).len() < 4 {
-                    result += 1;
+                if grid.is_roll(&current_coord, rule)
+                    && rule.accessible(grid.neighbors(&current_coord, rule).len())
+                {
                     roll_removed.push(current_coord);
                 }
             }
@@ -159,20 +512,195 @@ fn run(input: String) -> usize {
         }
 
         // Update the cells where roll is removed
+        let wave = history.waves.len() + 1;
         for cell in roll_removed.iter() {
             grid.set(cell, 'x');
+            history.removed_at.insert(*cell, wave);
         }
+        history.waves.push(roll_removed.len());
+    }
+
+    history.stable_core = grid
+        .data
+        .iter()
+        .filter(|(coord, _)| grid.is_roll(coord, rule))
+        .map(|(coord, _)| *coord)
+        .collect();
+
+    history
+}
+
+const RESET: &str = "\x1b[0m";
+const ROLL: &str = "\x1b[32m";
+const REMOVABLE: &str = "\x1b[1;31m";
+const REMOVED: &str = "\x1b[2;33m";
+
+/// Render the grid as it is at the start of `wave`, restricted to a viewport.
+/// Rolls removed during `wave` are highlighted, rolls removed before are `x`.
+fn render_wave(
+    grid: &Grid,
+    history: &RemovalHistory,
+    wave: usize,
+    origin: Coord,
+    width: usize,
+    height: usize,
+) -> String {
+    let mut output = String::new();
+    for y in origin.y..(origin.y + height as isize).min(grid.rows as isize) {
+        for x in origin.x..(origin.x + width as isize).min(grid.cols as isize) {
+            let coord = Coord::new(x, y);
+            match history.wave_of(&coord) {
+                Some(removed) if removed < wave => output.push_str(&format!("{REMOVED}x{RESET}")),
+                Some(removed) if removed == wave => {
+                    output.push_str(&format!("{REMOVABLE}@{RESET}"))
+                }
+                _ if grid.data[&coord] == '@' => output.push_str(&format!("{ROLL}@{RESET}")),
+                _ => output.push(grid.data[&coord]),
+            }
+        }
+        // Raw mode does not translate newlines
+        output.push_str("\r\n");
+    }
+    output
+}
+
+/// Interactive step / play / pause viewer drawn in place on the terminal.
+struct Viewer<'a> {
+    grid: &'a Grid,
+    history: &'a RemovalHistory,
+    wave: usize,
+    origin: Coord,
+    playing: bool,
+}
+
+impl Viewer<'_> {
+    fn last_wave(&self) -> usize {
+        self.history.waves.len() + 1
+    }
 
-        println!("-------------------------");
-        grid.print();
+    fn draw(&self, out: &mut impl Write) -> io::Result<()> {
+        let (width, height) = terminal::size()?;
+        let height = (height as usize).saturating_sub(1);
+        let removed: usize = self.history.waves.iter().take(self.wave - 1).sum();
+        let frame = render_wave(
+            self.grid,
+            self.history,
+            self.wave,
+            self.origin,
+            width as usize,
+            height,
+        );
+        execute!(
+            out,
+            cursor::MoveTo(0, 0),
+            terminal::Clear(terminal::ClearType::All)
+        )?;
+        write!(
+            out,
+            "{frame}wave {}/{} removed {} {} [space] play/pause [n/p] step [arrows] pan [q] quit",
+            self.wave,
+            self.last_wave(),
+            removed,
+            if self.playing { "playing" } else { "paused" },
+        )?;
+        out.flush()
+    }
+
+    fn pan(&mut self, dx: isize, dy: isize) -> io::Result<()> {
+        let (width, height) = terminal::size()?;
+        let max_x = (self.grid.cols as isize - width as isize).max(0);
+        let max_y = (self.grid.rows as isize - height as isize + 1).max(0);
+        self.origin = Coord::new(
+            (self.origin.x + dx).clamp(0, max_x),
+            (self.origin.y + dy).clamp(0, max_y),
+        );
+        Ok(())
+    }
+
+    fn event_loop(&mut self, out: &mut impl Write) -> io::Result<()> {
+        loop {
+            self.draw(out)?;
+            let timeout = if self.playing {
+                Duration::from_millis(200)
+            } else {
+                Duration::from_secs(3600)
+            };
+            if !event::poll(timeout)? {
+                // Timed out while playing, advance one wave. A paused viewer just waits
+                if self.playing {
+                    if self.wave < self.last_wave() {
+                        self.wave += 1;
+                    } else {
+                        self.playing = false;
+                    }
+                }
+                continue;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Char(' ') => self.playing = !self.playing,
+                KeyCode::Char('n') => self.wave = (self.wave + 1).min(self.last_wave()),
+                KeyCode::Char('p') => self.wave = (self.wave - 1).max(1),
+                KeyCode::Left => self.pan(-4, 0)?,
+                KeyCode::Right => self.pan(4, 0)?,
+                KeyCode::Up => self.pan(0, -4)?,
+                KeyCode::Down => self.pan(0, 4)?,
+                _ => {}
+            }
+        }
     }
-    dbg!(result)
+}
+
+fn view(grid: &Grid, history: &RemovalHistory) -> io::Result<()> {
+    let mut viewer = Viewer {
+        grid,
+        history,
+        wave: 1,
+        origin: Coord::default(),
+        playing: false,
+    };
+    let mut out = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+    let result = viewer.event_loop(&mut out);
+    execute!(out, cursor::Show, terminal::LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    result
 }
 
 fn main() {
     let input = read_input(None);
 
-    let answer = run(input);
+    let grid = Grid::from(input.as_str());
+    let history = simulate(&mut grid.clone(), &Rule::default());
+    let args: Vec<String> = env::args().collect();
+    if args.iter().any(|arg| arg == "--view") {
+        view(&grid, &history).expect("Terminal error");
+    } else if let Some(pos) = args.iter().position(|arg| arg == "--export") {
+        let dir = args.get(pos + 1).expect("Missing export directory");
+        let frames: Vec<Grid> = (0..=history.waves.len())
+            .map(|wave| history.grid_after(&grid, wave))
+            .collect();
+        let palette = Palette::new(
+            &[
+                ('.', [238, 238, 238]),
+                ('@', [139, 90, 43]),
+                ('x', [220, 60, 60]),
+            ],
+            [0, 0, 0],
+        );
+        export_frames(Path::new(dir), &frames, &palette).expect("Export failed");
+    } else {
+        print!("{}", history.heatmap_to_string());
+    }
+
+    let answer = history.total();
 
     println!("Answer: {}", answer);
 }
@@ -207,7 +735,175 @@ mod tests {
             "
         )));
         dbg!(&input);
-        let answer = run(input);
-        assert_eq!(answer, 43);
+        let history = simulate(&mut Grid::from(input.as_str()), &Rule::default());
+        assert_eq!(history.total(), 43);
+    }
+
+    #[test]
+    fn test_history() {
+        let input = indoc!(
+            r"
+            ..@@.@@@@.
+            @@@.@.@.@@
+            @@@@@.@.@@
+            @.@@@@..@.
+            @@.@@@@.@@
+            .@@@@@@@.@
+            .@.@.@.@@@
+            @.@@@.@@@@
+            .@@@@@@@@.
+            @.@.@@@.@.
+            "
+        );
+        let mut grid = Grid::from(input);
+        let rolls = input.chars().filter(|c| *c == '@').count();
+        let history = simulate(&mut grid, &Rule::default());
+        assert_eq!(history.waves[0], 13);
+        assert_eq!(history.total(), 43);
+        assert_eq!(history.stable_core.len(), rolls - 43);
+        assert_eq!(history.wave_of(&Coord::new(2, 0)), Some(1));
+        assert_eq!(history.wave_of(&Coord::new(0, 0)), None);
+
+        let heatmap = history.heatmap_to_string();
+        assert_eq!(heatmap.lines().count(), 10);
+        assert_eq!(heatmap.lines().next(), Some(". . 1 1 . 1 1 2 1 ."));
+        assert_eq!(heatmap.lines().nth(3), Some("2 . 6 9 @ @ . . 2 ."));
+    }
+
+    #[test]
+    fn test_rules() {
+        let input = indoc!(
+            r"
+            .....
+            .@@@.
+            .@@@.
+            .@@@.
+            .....
+            "
+        );
+
+        // Moore: corners have 3 neighbours, then edges drop to 2, then the centre.
+        let history = simulate(&mut Grid::from(input), &Rule::default());
+        assert_eq!(history.waves, vec![4, 4, 1]);
+
+        // Von Neumann with the same threshold: only the centre has 4 neighbours.
+        let rule = Rule {
+            neighborhood: Neighborhood::VonNeumann,
+            ..Default::default()
+        };
+        let history = simulate(&mut Grid::from(input), &rule);
+        assert_eq!(history.waves, vec![8, 1]);
+        let rule = Rule {
+            neighborhood: Neighborhood::VonNeumann,
+            threshold: 2,
+            ..Default::default()
+        };
+        let history = simulate(&mut Grid::from(input), &rule);
+        assert_eq!(history.total(), 0);
+        assert_eq!(history.stable_core.len(), 9);
+
+        // Custom horizontal neighbourhood, '#' also counts as occupied.
+        let rule = Rule {
+            neighborhood: Neighborhood::Custom(vec![(-1, 0).into(), (1, 0).into()]),
+            threshold: 2,
+            comparison: Comparison::Less,
+            occupied: "@#".to_string(),
+            ..Default::default()
+        };
+        let history = simulate(&mut Grid::from("@@@#"), &rule);
+        assert_eq!(history.waves, vec![2, 2]);
+
+        // Toroidal: a full row wraps around, so no cell ever has fewer than 2 neighbours.
+        let rule = Rule {
+            neighborhood: Neighborhood::Custom(vec![(-1, 0).into(), (1, 0).into()]),
+            threshold: 2,
+            toroidal: true,
+            ..Default::default()
+        };
+        let history = simulate(&mut Grid::from("@@@@"), &rule);
+        assert_eq!(history.total(), 0);
+        assert_eq!(history.stable_core.len(), 4);
+    }
+
+    #[test]
+    fn test_hex_neighbors() {
+        let grid = Grid::from(indoc!(
+            r"
+            @@@
+            @@@
+            @@@
+            "
+        ));
+        let rule = Rule {
+            neighborhood: Neighborhood::Hex,
+            ..Default::default()
+        };
+        assert_eq!(grid.neighbors(&Coord::new(1, 1), &rule).len(), 6);
+        assert_eq!(grid.neighbors(&Coord::new(0, 0), &rule).len(), 2);
+        assert_eq!(grid.neighbors(&Coord::new(0, 1), &rule).len(), 5);
+    }
+
+    #[test]
+    fn test_render_wave() {
+        let input = indoc!(
+            r"
+            .@@.
+            @@@@
+            "
+        );
+        let grid = Grid::from(input);
+        let history = simulate(&mut grid.clone(), &Rule::default());
+        assert_eq!(history.waves, vec![2, 4]);
+
+        let frame = render_wave(&grid, &history, 1, Coord::default(), 80, 24);
+        assert_eq!(frame.matches(REMOVABLE).count(), 2);
+        assert!(frame.starts_with(&format!(".{ROLL}@{RESET}")));
+
+        let frame = render_wave(&grid, &history, 2, Coord::default(), 80, 24);
+        assert_eq!(frame.matches(REMOVED).count(), 2);
+        assert_eq!(frame.matches(REMOVABLE).count(), 4);
+
+        // Viewport only keeps the second row and the last two columns
+        let frame = render_wave(&grid, &history, 3, Coord::new(2, 1), 80, 24);
+        assert_eq!(frame.lines().count(), 1);
+        assert_eq!(frame.matches('x').count(), 2);
+    }
+
+    #[test]
+    fn test_image_export() {
+        let grid = Grid::from(indoc!(
+            r"
+            .@@.
+            @@@@
+            "
+        ));
+        let history = simulate(&mut grid.clone(), &Rule::default());
+        let palette = Palette::new(&[('.', [255, 255, 255]), ('@', [0, 0, 0])], [255, 0, 0]);
+
+        let png = grid.to_png(&palette, 2).unwrap();
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+
+        let svg = grid.to_svg(&palette, 2).unwrap();
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(svg.matches("<rect").count(), 8);
+        assert_eq!(svg.matches("#000000").count(), 6);
+
+        let last = history.grid_after(&grid, history.waves.len());
+        assert_eq!(
+            last.to_svg(&palette, 2).unwrap().matches("#ff0000").count(),
+            6
+        );
+
+        let gif = frames_to_gif(&[grid.clone(), last], &palette, 2, 10).unwrap();
+        assert!(gif.starts_with(b"GIF89a"));
+        assert!(frames_to_gif(&[], &palette, 2, 10).is_err());
+
+        // Frame sizes and palette indexes that do not fit the image formats
+        assert!(grid.to_png(&palette, 1 << 31).is_err());
+        assert!(grid.to_svg(&palette, usize::MAX).is_err());
+        assert!(frames_to_gif(std::slice::from_ref(&grid), &palette, 1 << 14, 10).is_err());
+        let colors: Vec<(char, [u8; 3])> = ('\u{100}'..'\u{22c}').map(|c| (c, [0, 0, 0])).collect();
+        let large = Palette::new(&colors, [255, 255, 255]);
+        assert!(frames_to_gif(std::slice::from_ref(&grid), &large, 1, 10).is_err());
     }
 }
@@ -1,13 +1,266 @@
-use std::ops::RangeInclusive;
+use std::{
+    env, fmt, fs,
+    io::{self, BufRead, Write},
+    ops::RangeInclusive,
+};
 
 use nom::{
     IResult, Parser,
+    branch::alt,
+    bytes::complete::tag,
     character::complete::{digit1, line_ending, one_of},
     combinator::{all_consuming, map, map_res, opt},
     multi::many1,
-    sequence::separated_pair,
+    sequence::{preceded, separated_pair, terminated},
 };
 
+/// Integer-like bound usable in an `IntervalSet`.
+pub trait Discrete: Copy + Ord + std::fmt::Debug {
+    fn succ(self) -> Option<Self>;
+    /// Number of values in `start..=end`, `start <= end` is assumed.
+    fn count(start: Self, end: Self) -> u128;
+}
+
+impl Discrete for u64 {
+    fn succ(self) -> Option<Self> {
+        self.checked_add(1)
+    }
+
+    fn count(start: Self, end: Self) -> u128 {
+        (end - start) as u128 + 1
+    }
+}
+
+/// Sorted set of disjoint, non-adjacent inclusive intervals.
+/// The original ranges are kept (with their position in the input as ID)
+/// to be able to tell which ones cover a given value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntervalSet<T: Discrete> {
+    merged: Vec<RangeInclusive<T>>,
+    /// Original ranges sorted by start.
+    originals: Vec<(usize, RangeInclusive<T>)>,
+}
+
+impl<T: Discrete> IntervalSet<T> {
+    /// Sort and merge the ranges in O(n log n), empty ranges are ignored.
+    pub fn new(ranges: impl IntoIterator<Item = RangeInclusive<T>>) -> Self {
+        let mut originals: Vec<(usize, RangeInclusive<T>)> = ranges
+            .into_iter()
+            .enumerate()
+            .filter(|(_, range)| !range.is_empty())
+            .collect();
+        originals.sort_by_key(|(id, range)| (*range.start(), *range.end(), *id));
+
+        let mut merged: Vec<RangeInclusive<T>> = Vec::new();
+        for (_, range) in originals.iter() {
+            match merged.last_mut() {
+                // Overlapping or adjacent: extend the last interval
+                Some(last) if last.end().succ().is_none_or(|next| *range.start() <= next) => {
+                    if range.end() > last.end() {
+                        *last = *last.start()..=*range.end();
+                    }
+                }
+                _ => merged.push(range.clone()),
+            }
+        }
+
+        IntervalSet { merged, originals }
+    }
+
+    /// Index of the merged interval containing `value`, found by binary search.
+    fn position(&self, value: T) -> Option<usize> {
+        let idx = self
+            .merged
+            .partition_point(|range| *range.start() <= value)
+            .checked_sub(1)?;
+        self.merged[idx].contains(&value).then_some(idx)
+    }
+
+    pub fn contains(&self, value: T) -> bool {
+        self.position(value).is_some()
+    }
+
+    pub fn ranges(&self) -> &[RangeInclusive<T>] {
+        &self.merged
+    }
+
+    /// Number of distinct values covered by the set.
+    pub fn covered_len(&self) -> u128 {
+        self.merged
+            .iter()
+            .map(|range| T::count(*range.start(), *range.end()))
+            .sum()
+    }
+
+    /// IDs of the original ranges covering `value`, in increasing order.
+    pub fn covering_ids(&self, value: T) -> Vec<usize> {
+        let Some(idx) = self.position(value) else {
+            return Vec::new();
+        };
+        // Only the originals starting inside the merged interval can cover the value
+        let block_start = *self.merged[idx].start();
+        let from = self
+            .originals
+            .partition_point(|(_, range)| *range.start() < block_start);
+        let to = self
+            .originals
+            .partition_point(|(_, range)| *range.start() <= value);
+
+        let mut ids: Vec<usize> = self.originals[from..to]
+            .iter()
+            .filter(|(_, range)| range.contains(&value))
+            .map(|(id, _)| *id)
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+}
+
+/// Magic header of a saved range index.
+const INDEX_MAGIC: &[u8; 4] = b"D5IX";
+
+/// Binary index format: magic, merged interval count, then `start`/`end` pairs,
+/// all little-endian `u64`.
+impl IntervalSet<u64> {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = INDEX_MAGIC.to_vec();
+        bytes.extend_from_slice(&(self.merged.len() as u64).to_le_bytes());
+        for range in self.merged.iter() {
+            bytes.extend_from_slice(&range.start().to_le_bytes());
+            bytes.extend_from_slice(&range.end().to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let Some(body) = bytes.strip_prefix(INDEX_MAGIC) else {
+            return Err(invalid("Not a range index"));
+        };
+        let mut words = body
+            .chunks(8)
+            .map(|chunk| chunk.try_into().map(u64::from_le_bytes));
+        let count = words
+            .next()
+            .and_then(Result::ok)
+            .ok_or_else(|| invalid("Missing interval count"))?;
+        let expected = count.checked_mul(16).and_then(|n| n.checked_add(8));
+        if expected != Some(body.len() as u64) {
+            return Err(invalid("Truncated range index"));
+        }
+        let words: Vec<u64> = words.map(|word| word.unwrap()).collect();
+        Ok(IntervalSet::new(
+            words.chunks(2).map(|pair| pair[0]..=pair[1]),
+        ))
+    }
+}
+
+/// Range as written in the input, before validation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeSpec {
+    /// `a-b`
+    Inclusive(u64, u64),
+    /// `a..=b`
+    InclusiveDots(u64, u64),
+    /// `a..b`
+    HalfOpen(u64, u64),
+    /// `a-`
+    From(u64),
+    /// `-b`
+    To(u64),
+    /// `a`
+    Single(u64),
+}
+
+impl RangeSpec {
+    /// Convert to an inclusive range, `None` if the range is inverted or empty.
+    fn normalise(self) -> Option<RangeInclusive<u64>> {
+        match self {
+            RangeSpec::Inclusive(start, end) | RangeSpec::InclusiveDots(start, end) => {
+                (start <= end).then_some(start..=end)
+            }
+            RangeSpec::HalfOpen(start, end) => (start < end).then(|| start..=end - 1),
+            RangeSpec::From(start) => Some(start..=u64::MAX),
+            RangeSpec::To(end) => Some(0..=end),
+            RangeSpec::Single(value) => Some(value..=value),
+        }
+    }
+}
+
+impl fmt::Display for RangeSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RangeSpec::Inclusive(start, end) => write!(f, "{start}-{end}"),
+            RangeSpec::InclusiveDots(start, end) => write!(f, "{start}..={end}"),
+            RangeSpec::HalfOpen(start, end) => write!(f, "{start}..{end}"),
+            RangeSpec::From(start) => write!(f, "{start}-"),
+            RangeSpec::To(end) => write!(f, "-{end}"),
+            RangeSpec::Single(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+/// Range rejected by `validate`, `line` is 1-based within the range section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeError {
+    line: usize,
+    spec: RangeSpec,
+}
+
+impl fmt::Display for RangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self.spec {
+            RangeSpec::HalfOpen(start, end) if start == end => "empty",
+            _ => "inverted",
+        };
+        write!(f, "line {}: range {} is {}", self.line, self.spec, reason)
+    }
+}
+
+impl std::error::Error for RangeError {}
+
+/// Line of a range file that is not a range, `line` is 1-based.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidRange {
+    line: usize,
+    text: String,
+}
+
+impl fmt::Display for InvalidRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: invalid range {}", self.line, self.text)
+    }
+}
+
+impl std::error::Error for InvalidRange {}
+
+/// Normalise every range, reporting all the invalid ones.
+fn validate(specs: &[RangeSpec]) -> Result<Vec<RangeInclusive<u64>>, Vec<RangeError>> {
+    let mut ranges = Vec::new();
+    let mut errors = Vec::new();
+    for (idx, spec) in specs.iter().enumerate() {
+        match spec.normalise() {
+            Some(range) => ranges.push(range),
+            None => errors.push(RangeError {
+                line: idx + 1,
+                spec: *spec,
+            }),
+        }
+    }
+    if errors.is_empty() {
+        Ok(ranges)
+    } else {
+        Err(errors)
+    }
+}
+
+fn report<E: fmt::Display>(errors: impl IntoIterator<Item = E>) -> ! {
+    for error in errors {
+        eprintln!("{}", error);
+    }
+    std::process::exit(1);
+}
+
 fn read_input(input: Option<&str>) -> String {
     let input = match input {
         None => include_str!("../../input.txt"),
@@ -17,18 +270,48 @@ fn read_input(input: Option<&str>) -> String {
     input.to_string()
 }
 
-fn parse(input: &str) -> IResult<&str, Data> {
-    fn num(input: &str) -> IResult<&str, usize> {
-        map_res(digit1, str::parse).parse(input)
-    }
+fn num(input: &str) -> IResult<&str, u64> {
+    map_res(digit1, str::parse).parse(input)
+}
 
-    fn range(input: &str) -> IResult<&str, RangeInclusive<usize>> {
+fn range(input: &str) -> IResult<&str, RangeSpec> {
+    alt((
+        map(separated_pair(num, tag("..="), num), |(start, end)| {
+            RangeSpec::InclusiveDots(start, end)
+        }),
+        map(separated_pair(num, tag(".."), num), |(start, end)| {
+            RangeSpec::HalfOpen(start, end)
+        }),
         map(separated_pair(num, one_of("-"), num), |(start, end)| {
-            start..=end
+            RangeSpec::Inclusive(start, end)
+        }),
+        map(terminated(num, one_of("-")), RangeSpec::From),
+        map(preceded(one_of("-"), num), RangeSpec::To),
+        map(num, RangeSpec::Single),
+    ))
+    .parse(input)
+}
+
+/// Parse a range file up to its first blank line, so an ingredient list after the
+/// ranges is skipped. Every line before it must be a whole range.
+fn parse_ranges(input: &str) -> Result<Vec<RangeSpec>, InvalidRange> {
+    input
+        .lines()
+        .take_while(|line| !line.is_empty())
+        .enumerate()
+        .map(|(idx, line)| {
+            all_consuming(range)
+                .parse(line)
+                .map(|(_, spec)| spec)
+                .map_err(|_| InvalidRange {
+                    line: idx + 1,
+                    text: line.to_string(),
+                })
         })
-        .parse(input)
-    }
+        .collect()
+}
 
+fn parse(input: &str) -> IResult<&str, Data> {
     fn data(input: &str) -> IResult<&str, Data> {
         let (input, fresh_ingredients) =
             nom::multi::separated_list1(line_ending, range).parse(input)?;
@@ -50,28 +333,89 @@ fn parse(input: &str) -> IResult<&str, Data> {
 
 #[derive(Debug, PartialEq, Eq)]
 struct Data {
-    fresh_ingredients: Vec<RangeInclusive<usize>>,
-    ingredients: Vec<usize>,
+    fresh_ingredients: Vec<RangeSpec>,
+    ingredients: Vec<u64>,
 }
 
 fn run(input: String) -> usize {
     let (_, data) = parse(&input).unwrap();
-    let mut fresh_ingredients = 0;
     dbg!(&data);
 
-    for ingredient in data.ingredients.iter() {
-        for range in data.fresh_ingredients.iter() {
-            if range.contains(ingredient) {
-                fresh_ingredients += 1;
-                break;
-            }
+    let ranges = validate(&data.fresh_ingredients).unwrap_or_else(|errors| report(errors));
+    let fresh = IntervalSet::new(ranges);
+    let fresh_ingredients = data
+        .ingredients
+        .iter()
+        .filter(|ingredient| fresh.contains(**ingredient))
+        .count();
+
+    dbg!(fresh_ingredients)
+}
+
+/// Answer one ingredient ID per input line, with running totals.
+/// Returns the final `(fresh, spoiled)` counts.
+fn stream(
+    index: &IntervalSet<u64>,
+    input: impl BufRead,
+    mut output: impl Write,
+) -> io::Result<(usize, usize)> {
+    let (mut fresh, mut spoiled) = (0, 0);
+    for line in input.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
         }
+        let Ok(id) = line.parse::<u64>() else {
+            writeln!(output, "{line} invalid")?;
+            continue;
+        };
+        let status = if index.contains(id) {
+            fresh += 1;
+            "fresh"
+        } else {
+            spoiled += 1;
+            "spoiled"
+        };
+        writeln!(output, "{id} {status} (fresh: {fresh}, spoiled: {spoiled})")?;
     }
+    Ok((fresh, spoiled))
+}
 
-    dbg!(fresh_ingredients)
+/// `s1 (--ranges <file> | --load <index>) [--save <index>]`, then ingredient IDs on stdin.
+fn stream_mode(args: &[String]) -> io::Result<()> {
+    let option = |name: &str| {
+        args.iter()
+            .position(|arg| arg == name)
+            .and_then(|pos| args.get(pos + 1))
+    };
+
+    let index = if let Some(path) = option("--load") {
+        IntervalSet::from_bytes(&fs::read(path)?)?
+    } else if let Some(path) = option("--ranges") {
+        let input = fs::read_to_string(path)?;
+        let specs = parse_ranges(&input).unwrap_or_else(|error| report([error]));
+        IntervalSet::new(validate(&specs).unwrap_or_else(|errors| report(errors)))
+    } else {
+        return Err(io::Error::other("Missing --ranges or --load"));
+    };
+
+    if let Some(path) = option("--save") {
+        fs::write(path, index.to_bytes())?;
+    }
+
+    let (fresh, spoiled) = stream(&index, io::stdin().lock(), io::stdout().lock())?;
+    eprintln!("Total: {fresh} fresh, {spoiled} spoiled");
+    Ok(())
 }
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty() {
+        stream_mode(&args).expect("Streaming failed");
+        return;
+    }
+
     let input = read_input(None);
 
     let answer = run(input);
@@ -113,4 +457,104 @@ mod tests {
         let answer = run(input);
         assert_eq!(answer, 3);
     }
+
+    #[test]
+    fn test_interval_set() {
+        let set = IntervalSet::new(vec![3..=5, 10..=14, 16..=20, 12..=18, 6..=6]);
+        assert_eq!(set.ranges(), &[3..=6, 10..=20]);
+        assert_eq!(set.covered_len(), 15);
+        assert!(set.contains(3));
+        assert!(set.contains(6));
+        assert!(set.contains(15));
+        assert!(!set.contains(2));
+        assert!(!set.contains(7));
+        assert!(!set.contains(21));
+        assert_eq!(set.covering_ids(13), vec![1, 3]);
+        assert_eq!(set.covering_ids(17), vec![2, 3]);
+        assert_eq!(set.covering_ids(6), vec![4]);
+        assert_eq!(set.covering_ids(8), Vec::<usize>::new());
+
+        let set = IntervalSet::new(vec![0..=u64::MAX, 5..=6]);
+        assert_eq!(set.ranges(), &[0..=u64::MAX]);
+        assert_eq!(set.covered_len(), u64::MAX as u128 + 1);
+    }
+
+    #[test]
+    fn test_range_syntax() {
+        let (_, specs) = all_consuming(nom::multi::separated_list1(line_ending, range))
+            .parse("3-5\n3..=5\n3..6\n7-\n-2\n9")
+            .unwrap();
+        assert_eq!(
+            validate(&specs),
+            Ok(vec![3..=5, 3..=5, 3..=5, 7..=u64::MAX, 0..=2, 9..=9])
+        );
+
+        let (_, specs) = all_consuming(nom::multi::separated_list1(line_ending, range))
+            .parse("5-3\n1-2\n4..4\n8..=7")
+            .unwrap();
+        let errors: Vec<String> = validate(&specs)
+            .unwrap_err()
+            .iter()
+            .map(|error| error.to_string())
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                "line 1: range 5-3 is inverted",
+                "line 3: range 4..4 is empty",
+                "line 4: range 8..=7 is inverted",
+            ]
+        );
+
+        // A typo is reported instead of parsing as `10-` and dropping the rest
+        assert_eq!(
+            parse_ranges("3-5\n10-x\n12-18\n"),
+            Err(InvalidRange {
+                line: 2,
+                text: "10-x".to_string()
+            })
+        );
+        assert_eq!(
+            parse_ranges("3-5\n7..=9\n\n1\n5"),
+            Ok(vec![
+                RangeSpec::Inclusive(3, 5),
+                RangeSpec::InclusiveDots(7, 9)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_stream() {
+        let specs = parse_ranges("3-5\n10-14\n16-20\n12-18\n\n1\n5").unwrap();
+        let index = IntervalSet::new(validate(&specs).unwrap());
+
+        let bytes = index.to_bytes();
+        assert_eq!(bytes.len(), 4 + 8 + 2 * 16);
+        let loaded = IntervalSet::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.ranges(), index.ranges());
+        assert!(IntervalSet::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(IntervalSet::from_bytes(b"nope").is_err());
+        // A count whose byte length wraps around to the header size
+        let mut crafted = INDEX_MAGIC.to_vec();
+        crafted.extend_from_slice(&(1u64 << 60).to_le_bytes());
+        assert!(IntervalSet::from_bytes(&crafted).is_err());
+
+        let mut output = Vec::new();
+        let totals = stream(
+            &loaded,
+            "1\n5\n\nabc\n8\n11\n17\n32\n".as_bytes(),
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(totals, (3, 3));
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output.lines().take(3).collect::<Vec<_>>(),
+            vec![
+                "1 spoiled (fresh: 0, spoiled: 1)",
+                "5 fresh (fresh: 1, spoiled: 1)",
+                "abc invalid",
+            ]
+        );
+    }
 }
@@ -1,21 +1,301 @@
-use std::{collections::HashSet, ops::RangeInclusive};
+use std::{env, fmt, fs, ops::RangeInclusive};
 
 use nom::{
     IResult, Parser,
+    branch::alt,
+    bytes::complete::tag,
     character::complete::{digit1, line_ending, one_of},
     combinator::{all_consuming, map, map_res, opt},
     multi::many1,
-    sequence::separated_pair,
+    sequence::{preceded, separated_pair, terminated},
 };
 
-trait Lenght {
-    fn len(&self) -> usize;
+/// Integer-like bound usable in an `IntervalSet`.
+pub trait Discrete: Copy + Ord + std::fmt::Debug {
+    fn succ(self) -> Option<Self>;
+    fn pred(self) -> Option<Self>;
+    /// Number of values in `start..=end`, `start <= end` is assumed.
+    fn count(start: Self, end: Self) -> u128;
 }
 
-impl Lenght for RangeInclusive<usize> {
-    fn len(&self) -> usize {
-        self.end() - self.start() + 1
+impl Discrete for u64 {
+    fn succ(self) -> Option<Self> {
+        self.checked_add(1)
     }
+
+    fn pred(self) -> Option<Self> {
+        self.checked_sub(1)
+    }
+
+    fn count(start: Self, end: Self) -> u128 {
+        (end - start) as u128 + 1
+    }
+}
+
+/// Sorted set of disjoint, non-adjacent inclusive intervals.
+/// The original ranges are kept (with their position in the input as ID)
+/// to be able to tell which ones cover a given value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntervalSet<T: Discrete> {
+    merged: Vec<RangeInclusive<T>>,
+    /// Original ranges sorted by start.
+    originals: Vec<(usize, RangeInclusive<T>)>,
+}
+
+impl<T: Discrete> IntervalSet<T> {
+    /// Sort and merge the ranges in O(n log n), empty ranges are ignored.
+    pub fn new(ranges: impl IntoIterator<Item = RangeInclusive<T>>) -> Self {
+        let mut originals: Vec<(usize, RangeInclusive<T>)> = ranges
+            .into_iter()
+            .enumerate()
+            .filter(|(_, range)| !range.is_empty())
+            .collect();
+        originals.sort_by_key(|(id, range)| (*range.start(), *range.end(), *id));
+
+        let mut merged: Vec<RangeInclusive<T>> = Vec::new();
+        for (_, range) in originals.iter() {
+            match merged.last_mut() {
+                // Overlapping or adjacent: extend the last interval
+                Some(last) if last.end().succ().is_none_or(|next| *range.start() <= next) => {
+                    if range.end() > last.end() {
+                        *last = *last.start()..=*range.end();
+                    }
+                }
+                _ => merged.push(range.clone()),
+            }
+        }
+
+        IntervalSet { merged, originals }
+    }
+
+    /// Index of the merged interval containing `value`, found by binary search.
+    fn position(&self, value: T) -> Option<usize> {
+        let idx = self
+            .merged
+            .partition_point(|range| *range.start() <= value)
+            .checked_sub(1)?;
+        self.merged[idx].contains(&value).then_some(idx)
+    }
+
+    pub fn contains(&self, value: T) -> bool {
+        self.position(value).is_some()
+    }
+
+    pub fn ranges(&self) -> &[RangeInclusive<T>] {
+        &self.merged
+    }
+
+    /// Number of distinct values covered by the set.
+    pub fn covered_len(&self) -> u128 {
+        self.merged
+            .iter()
+            .map(|range| T::count(*range.start(), *range.end()))
+            .sum()
+    }
+
+    /// IDs of the original ranges covering `value`, in increasing order.
+    pub fn covering_ids(&self, value: T) -> Vec<usize> {
+        let Some(idx) = self.position(value) else {
+            return Vec::new();
+        };
+        // Only the originals starting inside the merged interval can cover the value
+        let block_start = *self.merged[idx].start();
+        let from = self
+            .originals
+            .partition_point(|(_, range)| *range.start() < block_start);
+        let to = self
+            .originals
+            .partition_point(|(_, range)| *range.start() <= value);
+
+        let mut ids: Vec<usize> = self.originals[from..to]
+            .iter()
+            .filter(|(_, range)| range.contains(&value))
+            .map(|(id, _)| *id)
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        IntervalSet::new(self.merged.iter().chain(other.merged.iter()).cloned())
+    }
+
+    pub fn intersection(&self, other: &Self) -> Self {
+        let mut result = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < self.merged.len() && j < other.merged.len() {
+            let (a, b) = (&self.merged[i], &other.merged[j]);
+            let start = *a.start().max(b.start());
+            let end = *a.end().min(b.end());
+            if start <= end {
+                result.push(start..=end);
+            }
+            // Move past the interval that ends first
+            if a.end() < b.end() {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        IntervalSet::new(result)
+    }
+
+    /// Values of `bounds` not covered by the set.
+    pub fn complement(&self, bounds: RangeInclusive<T>) -> Self {
+        let mut result = Vec::new();
+        let mut cursor = Some(*bounds.start());
+        for range in self.merged.iter() {
+            let Some(start) = cursor else {
+                break;
+            };
+            if range.end() < &start {
+                continue;
+            }
+            if range.start() > bounds.end() {
+                break;
+            }
+            if *range.start() > start {
+                // range.start() > start >= T::MIN so pred always exists
+                result.push(start..=range.start().pred().unwrap());
+            }
+            cursor = range.end().succ();
+        }
+        if let Some(start) = cursor
+            && start <= *bounds.end()
+        {
+            result.push(start..=*bounds.end());
+        }
+        IntervalSet::new(result)
+    }
+
+    pub fn difference(&self, other: &Self) -> Self {
+        match (self.merged.first(), self.merged.last()) {
+            (Some(first), Some(last)) => {
+                self.intersection(&other.complement(*first.start()..=*last.end()))
+            }
+            _ => self.clone(),
+        }
+    }
+
+    pub fn symmetric_difference(&self, other: &Self) -> Self {
+        self.difference(other).union(&other.difference(self))
+    }
+}
+
+/// One `a-b` line per merged interval, as in the input.
+impl<T: Discrete + fmt::Display> fmt::Display for IntervalSet<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for range in self.merged.iter() {
+            writeln!(f, "{}-{}", range.start(), range.end())?;
+        }
+        Ok(())
+    }
+}
+
+/// Range as written in the input, before validation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeSpec {
+    /// `a-b`
+    Inclusive(u64, u64),
+    /// `a..=b`
+    InclusiveDots(u64, u64),
+    /// `a..b`
+    HalfOpen(u64, u64),
+    /// `a-`
+    From(u64),
+    /// `-b`
+    To(u64),
+    /// `a`
+    Single(u64),
+}
+
+impl RangeSpec {
+    /// Convert to an inclusive range, `None` if the range is inverted or empty.
+    fn normalise(self) -> Option<RangeInclusive<u64>> {
+        match self {
+            RangeSpec::Inclusive(start, end) | RangeSpec::InclusiveDots(start, end) => {
+                (start <= end).then_some(start..=end)
+            }
+            RangeSpec::HalfOpen(start, end) => (start < end).then(|| start..=end - 1),
+            RangeSpec::From(start) => Some(start..=u64::MAX),
+            RangeSpec::To(end) => Some(0..=end),
+            RangeSpec::Single(value) => Some(value..=value),
+        }
+    }
+}
+
+impl fmt::Display for RangeSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RangeSpec::Inclusive(start, end) => write!(f, "{start}-{end}"),
+            RangeSpec::InclusiveDots(start, end) => write!(f, "{start}..={end}"),
+            RangeSpec::HalfOpen(start, end) => write!(f, "{start}..{end}"),
+            RangeSpec::From(start) => write!(f, "{start}-"),
+            RangeSpec::To(end) => write!(f, "-{end}"),
+            RangeSpec::Single(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+/// Range rejected by `validate`, `line` is 1-based within the range section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeError {
+    line: usize,
+    spec: RangeSpec,
+}
+
+impl fmt::Display for RangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self.spec {
+            RangeSpec::HalfOpen(start, end) if start == end => "empty",
+            _ => "inverted",
+        };
+        write!(f, "line {}: range {} is {}", self.line, self.spec, reason)
+    }
+}
+
+impl std::error::Error for RangeError {}
+
+/// Line of a range file that is not a range, `line` is 1-based.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidRange {
+    line: usize,
+    text: String,
+}
+
+impl fmt::Display for InvalidRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: invalid range {}", self.line, self.text)
+    }
+}
+
+impl std::error::Error for InvalidRange {}
+
+/// Normalise every range, reporting all the invalid ones.
+fn validate(specs: &[RangeSpec]) -> Result<Vec<RangeInclusive<u64>>, Vec<RangeError>> {
+    let mut ranges = Vec::new();
+    let mut errors = Vec::new();
+    for (idx, spec) in specs.iter().enumerate() {
+        match spec.normalise() {
+            Some(range) => ranges.push(range),
+            None => errors.push(RangeError {
+                line: idx + 1,
+                spec: *spec,
+            }),
+        }
+    }
+    if errors.is_empty() {
+        Ok(ranges)
+    } else {
+        Err(errors)
+    }
+}
+
+fn report<E: fmt::Display>(errors: impl IntoIterator<Item = E>) -> ! {
+    for error in errors {
+        eprintln!("{}", error);
+    }
+    std::process::exit(1);
 }
 
 fn read_input(input: Option<&str>) -> String {
@@ -27,18 +307,29 @@ fn read_input(input: Option<&str>) -> String {
     input.to_string()
 }
 
-fn parse(input: &str) -> IResult<&str, Data> {
-    fn num(input: &str) -> IResult<&str, usize> {
-        map_res(digit1, str::parse).parse(input)
-    }
+fn num(input: &str) -> IResult<&str, u64> {
+    map_res(digit1, str::parse).parse(input)
+}
 
-    fn range(input: &str) -> IResult<&str, RangeInclusive<usize>> {
+fn range(input: &str) -> IResult<&str, RangeSpec> {
+    alt((
+        map(separated_pair(num, tag("..="), num), |(start, end)| {
+            RangeSpec::InclusiveDots(start, end)
+        }),
+        map(separated_pair(num, tag(".."), num), |(start, end)| {
+            RangeSpec::HalfOpen(start, end)
+        }),
         map(separated_pair(num, one_of("-"), num), |(start, end)| {
-            start..=end
-        })
-        .parse(input)
-    }
+            RangeSpec::Inclusive(start, end)
+        }),
+        map(terminated(num, one_of("-")), RangeSpec::From),
+        map(preceded(one_of("-"), num), RangeSpec::To),
+        map(num, RangeSpec::Single),
+    ))
+    .parse(input)
+}
 
+fn parse(input: &str) -> IResult<&str, Data> {
     fn data(input: &str) -> IResult<&str, Data> {
         let (input, fresh_ingredients) =
             nom::multi::separated_list1(line_ending, range).parse(input)?;
@@ -58,55 +349,78 @@ fn parse(input: &str) -> IResult<&str, Data> {
     all_consuming(data).parse(input)
 }
 
+/// Parse a range file up to its first blank line, so an ingredient list after the
+/// ranges is skipped. Every line before it must be a whole range.
+fn parse_ranges(input: &str) -> Result<Vec<RangeSpec>, InvalidRange> {
+    input
+        .lines()
+        .take_while(|line| !line.is_empty())
+        .enumerate()
+        .map(|(idx, line)| {
+            all_consuming(range)
+                .parse(line)
+                .map(|(_, spec)| spec)
+                .map_err(|_| InvalidRange {
+                    line: idx + 1,
+                    text: line.to_string(),
+                })
+        })
+        .collect()
+}
+
 #[derive(Debug, PartialEq, Eq)]
 struct Data {
-    fresh_ingredients: Vec<RangeInclusive<usize>>,
-    ingredients: Vec<usize>,
+    fresh_ingredients: Vec<RangeSpec>,
+    ingredients: Vec<u64>,
 }
 
-fn run(input: String) -> usize {
+fn run(input: String) -> u128 {
     let (_, data) = parse(&input).unwrap();
     dbg!(&data);
 
-    let mut ranges = data.fresh_ingredients.clone();
-    let mut hashset = HashSet::new();
-
-    loop {
-        let mut refined_ranges: Vec<RangeInclusive<usize>> = Vec::new();
-        for range in ranges.iter() {
-            let mut start = range.start();
-            let mut end = range.end();
-            for rc in ranges.iter() {
-                if rc.contains(start) && rc.start() < start {
-                    start = rc.start();
-                }
-
-                if rc.contains(end) && rc.end() > end {
-                    end = rc.end();
-                }
-            }
-            refined_ranges.push(RangeInclusive::new(*start, *end));
-        }
-
-        if ranges == refined_ranges {
-            break;
-        }
-
-        ranges = refined_ranges.clone();
-    }
-
-    dbg!(&ranges);
+    let ranges = validate(&data.fresh_ingredients).unwrap_or_else(|errors| report(errors));
+    let fresh = IntervalSet::new(ranges);
+    dbg!(fresh.ranges());
 
-    for range in ranges.iter() {
-        hashset.insert(range);
-    }
+    fresh.covered_len()
+}
 
-    dbg!(&hashset);
+fn read_range_file(path: &str) -> IntervalSet<u64> {
+    let input = fs::read_to_string(path).expect("Cannot read range file");
+    let specs = parse_ranges(&input).unwrap_or_else(|error| report([error]));
+    IntervalSet::new(validate(&specs).unwrap_or_else(|errors| report(errors)))
+}
 
-    hashset.iter().map(|o| o.len()).sum()
+/// `s2 <union|intersection|difference|symmetric-difference> a.txt b.txt`
+/// or `s2 complement a.txt <start>-<end>`.
+fn set_operation(args: &[String]) -> Option<IntervalSet<u64>> {
+    let [op, a, b] = args else {
+        return None;
+    };
+    let a = read_range_file(a);
+    let result = match op.as_str() {
+        "union" => a.union(&read_range_file(b)),
+        "intersection" => a.intersection(&read_range_file(b)),
+        "difference" => a.difference(&read_range_file(b)),
+        "symmetric-difference" => a.symmetric_difference(&read_range_file(b)),
+        "complement" => {
+            let (_, bounds) = all_consuming(range).parse(b).expect("Invalid bounds");
+            let bounds = validate(&[bounds]).unwrap_or_else(|errors| report(errors));
+            a.complement(bounds[0].clone())
+        }
+        _ => return None,
+    };
+    Some(result)
 }
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty() {
+        let result = set_operation(&args).expect("Unknown set operation");
+        print!("{}", result);
+        return;
+    }
+
     let input = read_input(None);
 
     let answer = run(input);
@@ -148,4 +462,103 @@ mod tests {
         let answer = run(input);
         assert_eq!(answer, 14);
     }
+
+    #[test]
+    fn test_interval_set() {
+        let set = IntervalSet::new(vec![3..=5, 10..=14, 16..=20, 12..=18, 6..=6]);
+        assert_eq!(set.ranges(), &[3..=6, 10..=20]);
+        assert_eq!(set.covered_len(), 15);
+        assert!(set.contains(3));
+        assert!(set.contains(6));
+        assert!(set.contains(15));
+        assert!(!set.contains(2));
+        assert!(!set.contains(7));
+        assert!(!set.contains(21));
+        assert_eq!(set.covering_ids(13), vec![1, 3]);
+        assert_eq!(set.covering_ids(17), vec![2, 3]);
+        assert_eq!(set.covering_ids(6), vec![4]);
+        assert_eq!(set.covering_ids(8), Vec::<usize>::new());
+
+        let set = IntervalSet::new(vec![0..=u64::MAX, 5..=6]);
+        assert_eq!(set.ranges(), &[0..=u64::MAX]);
+        assert_eq!(set.covered_len(), u64::MAX as u128 + 1);
+    }
+
+    #[test]
+    fn test_interval_algebra() {
+        let yesterday = parse_ranges("3-5\n10-14\n16-20\n12-18\n\n1\n5").unwrap();
+        let today = parse_ranges("1-4\n15..26\n30").unwrap();
+        let yesterday = IntervalSet::new(validate(&yesterday).unwrap());
+        let today = IntervalSet::new(validate(&today).unwrap());
+
+        // Compare every operation with the naive per-value result
+        let check = |set: &IntervalSet<u64>, expected: &dyn Fn(u64) -> bool| {
+            for value in 0..40 {
+                assert_eq!(set.contains(value), expected(value), "value {value}");
+            }
+        };
+        let (y, t) = (&yesterday, &today);
+        check(&y.union(t), &|v| y.contains(v) || t.contains(v));
+        check(&y.intersection(t), &|v| y.contains(v) && t.contains(v));
+        check(&y.difference(t), &|v| y.contains(v) && !t.contains(v));
+        check(&t.difference(y), &|v| t.contains(v) && !y.contains(v));
+        check(&y.symmetric_difference(t), &|v| {
+            y.contains(v) != t.contains(v)
+        });
+        check(&y.complement(0..=35), &|v| v <= 35 && !y.contains(v));
+
+        assert_eq!(t.difference(y).to_string(), "1-2\n21-25\n30-30\n");
+        assert_eq!(y.complement(4..=12).to_string(), "6-9\n");
+
+        let full = IntervalSet::new(vec![0..=u64::MAX]);
+        assert_eq!(full.complement(0..=u64::MAX).ranges(), &[]);
+        assert_eq!(
+            y.complement(0..=u64::MAX).ranges().last(),
+            Some(&(21..=u64::MAX))
+        );
+    }
+
+    #[test]
+    fn test_range_syntax() {
+        let (_, specs) = all_consuming(nom::multi::separated_list1(line_ending, range))
+            .parse("3-5\n3..=5\n3..6\n7-\n-2\n9")
+            .unwrap();
+        assert_eq!(
+            validate(&specs),
+            Ok(vec![3..=5, 3..=5, 3..=5, 7..=u64::MAX, 0..=2, 9..=9])
+        );
+
+        let (_, specs) = all_consuming(nom::multi::separated_list1(line_ending, range))
+            .parse("5-3\n1-2\n4..4\n8..=7")
+            .unwrap();
+        let errors: Vec<String> = validate(&specs)
+            .unwrap_err()
+            .iter()
+            .map(|error| error.to_string())
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                "line 1: range 5-3 is inverted",
+                "line 3: range 4..4 is empty",
+                "line 4: range 8..=7 is inverted",
+            ]
+        );
+
+        // A typo is reported instead of parsing as `10-` and dropping the rest
+        assert_eq!(
+            parse_ranges("3-5\n10-x\n12-18\n"),
+            Err(InvalidRange {
+                line: 2,
+                text: "10-x".to_string()
+            })
+        );
+        assert_eq!(
+            parse_ranges("3-5\n7..=9\n\n1\n5"),
+            Ok(vec![
+                RangeSpec::Inclusive(3, 5),
+                RangeSpec::InclusiveDots(7, 9)
+            ])
+        );
+    }
 }
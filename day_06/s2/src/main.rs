@@ -1,3 +1,9 @@
+use std::{collections::HashMap, env, fmt, ops::Range};
+
+use num_bigint::BigInt;
+use num_traits::Zero;
+use serde::Serialize;
+
 fn read_input(input: Option<&str>) -> String {
     let input = match input {
         None => include_str!("../../input.txt"),
@@ -7,76 +13,604 @@ fn read_input(input: Option<&str>) -> String {
     input.to_string()
 }
 
-fn run(input: String) -> usize {
-    let mut lines: Vec<&str> = input
-        .lines()
-        .filter(|line| !line.trim().is_empty()) // There is a fu..... trailing whitespace !
-        .collect();
-    assert!(!lines.is_empty(), "input should contain at least one line");
-
-    let ops_line = lines.pop().expect("operations line missing");
-    let ops: Vec<char> = ops_line
-        .split_whitespace()
-        .map(|s| {
-            let ch = s.chars().next().expect("empty operator token");
-            assert!(ch == '+' || ch == '*', "unsupported operator {}", ch);
-            ch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalError {
+    UnknownOperator(String),
+    /// Operator without identity element applied to no number.
+    NoOperand(String),
+    Overflow,
+    DivisionByZero,
+    /// Cell that is not a number, or too large for `usize`.
+    InvalidNumber(String),
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::UnknownOperator(op) => write!(f, "unsupported operator {}", op),
+            EvalError::NoOperand(op) => write!(f, "no operand for operator {}", op),
+            EvalError::Overflow => write!(f, "arithmetic overflow"),
+            EvalError::DivisionByZero => write!(f, "division by zero"),
+            EvalError::InvalidNumber(text) => write!(f, "invalid number {}", text),
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+/// Binary operator folded left over the numbers of a problem.
+/// `native` uses checked `usize` arithmetic, `big` is the arbitrary-precision version.
+#[derive(Clone, Copy)]
+pub struct Operator {
+    identity: Option<usize>,
+    native: fn(usize, usize) -> Result<usize, EvalError>,
+    big: fn(&BigInt, &BigInt) -> Result<BigInt, EvalError>,
+}
+
+/// Operators available to the worksheets, by symbol.
+pub struct Registry {
+    operators: HashMap<String, Operator>,
+}
+
+impl Registry {
+    pub fn register(&mut self, symbol: &str, operator: Operator) {
+        self.operators.insert(symbol.to_string(), operator);
+    }
+
+    fn get(&self, symbol: &str) -> Result<&Operator, EvalError> {
+        self.operators
+            .get(symbol)
+            .ok_or_else(|| EvalError::UnknownOperator(symbol.to_string()))
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        let mut registry = Registry {
+            operators: HashMap::new(),
+        };
+        registry.register(
+            "+",
+            Operator {
+                identity: Some(0),
+                native: |a, b| a.checked_add(b).ok_or(EvalError::Overflow),
+                big: |a, b| Ok(a + b),
+            },
+        );
+        registry.register(
+            "*",
+            Operator {
+                identity: Some(1),
+                native: |a, b| a.checked_mul(b).ok_or(EvalError::Overflow),
+                big: |a, b| Ok(a * b),
+            },
+        );
+        registry.register(
+            "-",
+            Operator {
+                identity: None,
+                native: |a, b| a.checked_sub(b).ok_or(EvalError::Overflow),
+                big: |a, b| Ok(a - b),
+            },
+        );
+        registry.register(
+            "/",
+            Operator {
+                identity: None,
+                native: |a, b| a.checked_div(b).ok_or(EvalError::DivisionByZero),
+                big: |a, b| {
+                    if b.is_zero() {
+                        Err(EvalError::DivisionByZero)
+                    } else {
+                        Ok(a / b)
+                    }
+                },
+            },
+        );
+        registry.register(
+            "min",
+            Operator {
+                identity: None,
+                native: |a, b| Ok(a.min(b)),
+                big: |a, b| Ok(a.min(b).clone()),
+            },
+        );
+        registry.register(
+            "max",
+            Operator {
+                identity: Some(0),
+                native: |a, b| Ok(a.max(b)),
+                big: |a, b| Ok(a.max(b).clone()),
+            },
+        );
+        registry.register(
+            "^",
+            Operator {
+                identity: None,
+                native: |a, b| {
+                    let exp = u32::try_from(b).map_err(|_| EvalError::Overflow)?;
+                    a.checked_pow(exp).ok_or(EvalError::Overflow)
+                },
+                big: |a, b| {
+                    let exp = u32::try_from(b).map_err(|_| EvalError::Overflow)?;
+                    Ok(a.pow(exp))
+                },
+            },
+        );
+        registry
+    }
+}
+
+/// One problem of the worksheet: an operator applied to its numbers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    op: String,
+    numbers: Vec<usize>,
+    /// First cell that could not be read as a number, it fails the problem.
+    invalid: Option<String>,
+}
+
+impl Problem {
+    /// Problem over the trimmed number cells of a block.
+    fn new(op: &str, cells: impl IntoIterator<Item = String>) -> Problem {
+        let mut numbers = Vec::new();
+        let mut invalid = None;
+        for cell in cells {
+            match cell.parse() {
+                Ok(number) => numbers.push(number),
+                Err(_) => {
+                    invalid.get_or_insert(cell);
+                }
+            }
+        }
+        Problem {
+            op: op.to_string(),
+            numbers,
+            invalid,
+        }
+    }
+
+    /// Start value of the fold, the identity element or the first number.
+    fn operands(&self, operator: &Operator) -> Result<(usize, &[usize]), EvalError> {
+        if let Some(cell) = &self.invalid {
+            return Err(EvalError::InvalidNumber(cell.clone()));
+        }
+        match (operator.identity, self.numbers.split_first()) {
+            (Some(identity), _) => Ok((identity, &self.numbers)),
+            (None, Some((first, rest))) => Ok((*first, rest)),
+            (None, None) => Err(EvalError::NoOperand(self.op.clone())),
+        }
+    }
+
+    fn solve(&self, registry: &Registry) -> Result<usize, EvalError> {
+        let operator = registry.get(&self.op)?;
+        let (init, rest) = self.operands(operator)?;
+        rest.iter()
+            .try_fold(init, |acc, number| (operator.native)(acc, *number))
+    }
+
+    fn solve_big(&self, registry: &Registry) -> Result<BigInt, EvalError> {
+        let operator = registry.get(&self.op)?;
+        let (init, rest) = self.operands(operator)?;
+        rest.iter().try_fold(BigInt::from(init), |acc, number| {
+            (operator.big)(&acc, &BigInt::from(*number))
         })
-        .collect();
+    }
+}
 
-    let max_width = lines.iter().map(|line| line.len()).max().unwrap_or(0);
-    let mut part2 = 0usize;
-    let mut acc = 0usize;
-    let mut op_idx = 0usize;
+/// How the numbers of a block are read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reading {
+    Rows,
+    Columns,
+}
 
-    for col_idx in 0..max_width {
-        let mut col_chars = String::new();
-        for line in &lines {
-            let ch = line.chars().nth(col_idx).unwrap_or(' ');
-            col_chars.push(ch);
+/// How one block was interpreted, for the explain mode.
+#[derive(Debug, Serialize)]
+pub struct Explanation {
+    index: usize,
+    /// Column span, end excluded.
+    columns: (usize, usize),
+    op: String,
+    /// Numbers in reading order.
+    numbers: Vec<usize>,
+    result: Option<usize>,
+    error: Option<String>,
+    text: Vec<String>,
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Problem {} (columns {}..{}): {}",
+            self.index, self.columns.0, self.columns.1, self.op
+        )?;
+        for line in self.text.iter() {
+            writeln!(f, "  | {}", line)?;
+        }
+        let numbers: Vec<String> = self.numbers.iter().map(|n| n.to_string()).collect();
+        writeln!(f, "  numbers: {}", numbers.join(&format!(" {} ", self.op)))?;
+        match (&self.result, &self.error) {
+            (Some(result), _) => writeln!(f, "  result: {}", result),
+            (_, Some(error)) => writeln!(f, "  error: {}", error),
+            _ => Ok(()),
         }
+    }
+}
 
-        dbg!(&col_chars);
+const DEFAULT_TAB_WIDTH: usize = 8;
 
-        let is_blank_column = col_chars.chars().all(|c| c.is_whitespace());
-        if is_blank_column {
-            if acc != 0 {
-                part2 += acc;
-                acc = 0;
+/// Split into lines without CR, tabs expanded to the next multiple of `tab_width`.
+/// A width of 0 is treated as 1, each tab then becomes a single space.
+fn normalise(input: &str, tab_width: usize) -> Vec<Vec<char>> {
+    let tab_width = tab_width.max(1);
+    input
+        .lines()
+        .map(|line| {
+            let mut row = Vec::new();
+            for c in line.chars().filter(|c| *c != '\r') {
+                if c == '\t' {
+                    let spaces = tab_width - row.len() % tab_width;
+                    row.extend(std::iter::repeat_n(' ', spaces));
+                } else {
+                    row.push(c);
+                }
             }
-            if op_idx + 1 < ops.len() {
-                op_idx += 1;
+            row
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// No operator starts under the block, the one with the same index is used.
+    MissingOperator { block: usize, columns: Range<usize> },
+    /// Several operators start under the block, the first one is used.
+    ExtraOperator {
+        block: usize,
+        op: String,
+        column: usize,
+    },
+    /// Operator that does not start under any block.
+    StrayOperator { op: String, column: usize },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::MissingOperator { block, columns } => write!(
+                f,
+                "no operator under problem {} (columns {}..{})",
+                block, columns.start, columns.end
+            ),
+            Warning::ExtraOperator { block, op, column } => write!(
+                f,
+                "extra operator {} at column {} under problem {}",
+                op, column, block
+            ),
+            Warning::StrayOperator { op, column } => {
+                write!(
+                    f,
+                    "operator {} at column {} is not under a problem",
+                    op, column
+                )
             }
-            continue;
         }
+    }
+}
 
-        let value_str = col_chars.trim();
-        if value_str.is_empty() {
+/// Operator tokens of the operator row, with their starting column.
+fn operator_tokens(ops_row: &[char]) -> Vec<(usize, String)> {
+    let mut tokens: Vec<(usize, String)> = Vec::new();
+    for (col, c) in ops_row.iter().enumerate() {
+        if c.is_whitespace() {
             continue;
         }
+        match tokens.last_mut() {
+            Some((start, token)) if *start + token.chars().count() == col => token.push(*c),
+            _ => tokens.push((col, c.to_string())),
+        }
+    }
+    tokens
+}
 
-        let value = value_str.parse::<usize>().unwrap();
-        dbg!(&value);
-        match ops[op_idx] {
-            '+' => acc += value,
-            '*' => acc = if acc == 0 { value } else { acc * value },
-            _ => unreachable!(),
+/// Pick the operator starting under each block, warning about the ones that do not line up.
+fn align_operators(ops_row: &[char], blocks: &[Range<usize>]) -> (Vec<String>, Vec<Warning>) {
+    let tokens = operator_tokens(ops_row);
+    let mut warnings = Vec::new();
+    let mut ops = Vec::new();
+
+    for (idx, block) in blocks.iter().enumerate() {
+        let mut under = tokens.iter().filter(|(col, _)| block.contains(col));
+        match under.next() {
+            Some((_, op)) => ops.push(op.clone()),
+            None => {
+                warnings.push(Warning::MissingOperator {
+                    block: idx,
+                    columns: block.clone(),
+                });
+                // Fall back to the operator with the same index, if any
+                ops.push(
+                    tokens
+                        .get(idx)
+                        .map(|(_, op)| op.clone())
+                        .unwrap_or_default(),
+                );
+            }
+        }
+        for (column, op) in under {
+            warnings.push(Warning::ExtraOperator {
+                block: idx,
+                op: op.clone(),
+                column: *column,
+            });
         }
     }
 
-    part2 += acc;
+    for (column, op) in tokens.iter() {
+        if !blocks.iter().any(|block| block.contains(column)) {
+            warnings.push(Warning::StrayOperator {
+                op: op.clone(),
+                column: *column,
+            });
+        }
+    }
 
-    dbg!(part2);
+    (ops, warnings)
+}
 
-    part2
+/// Worksheet parsed once as a character grid.
+/// Problems are blocks of columns separated by blank columns.
+#[derive(Debug)]
+pub struct Worksheet {
+    /// Number rows, padded with spaces to the same width.
+    rows: Vec<Vec<char>>,
+    /// Operator row as written, padded like the number rows.
+    ops_row: Vec<char>,
+    /// One operator per block, left to right.
+    ops: Vec<String>,
+    /// Column span of each problem block.
+    blocks: Vec<Range<usize>>,
+    /// Operators that do not line up with the number blocks.
+    warnings: Vec<Warning>,
+}
+
+impl Worksheet {
+    pub fn parse(input: &str) -> Worksheet {
+        Worksheet::parse_with_tab_width(input, DEFAULT_TAB_WIDTH)
+    }
+
+    pub fn parse_with_tab_width(input: &str, tab_width: usize) -> Worksheet {
+        let mut lines: Vec<Vec<char>> = normalise(input, tab_width)
+            .into_iter()
+            .filter(|line| !line.iter().all(|c| c.is_whitespace())) // There is a fu..... trailing whitespace !
+            .collect();
+        assert!(!lines.is_empty(), "input should contain at least one line");
+
+        let mut ops_row = lines.pop().expect("operations line missing");
+
+        // Pad ragged lines
+        let width = lines.iter().map(|line| line.len()).max().unwrap_or(0);
+        ops_row.resize(width.max(ops_row.len()), ' ');
+        let rows: Vec<Vec<char>> = lines
+            .into_iter()
+            .map(|mut row| {
+                row.resize(width, ' ');
+                row
+            })
+            .collect();
+
+        let mut blocks = Vec::new();
+        let mut start = None;
+        for col in 0..=width {
+            let blank = col == width || rows.iter().all(|row| row[col].is_whitespace());
+            match (blank, start) {
+                (false, None) => start = Some(col),
+                (true, Some(block_start)) => {
+                    blocks.push(block_start..col);
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+
+        let (ops, warnings) = align_operators(&ops_row, &blocks);
+
+        Worksheet {
+            rows,
+            ops_row,
+            ops,
+            blocks,
+            warnings,
+        }
+    }
+
+    /// Part 1: each row of a block is a number. Ragged rows that stop
+    /// before a block contribute no number to it.
+    pub fn row_wise(&self) -> Vec<Problem> {
+        self.blocks
+            .iter()
+            .zip(self.ops.iter())
+            .map(|(block, op)| {
+                let cells = self.rows.iter().filter_map(|row| {
+                    let number: String = row[block.clone()].iter().collect();
+                    let number = number.trim();
+                    (!number.is_empty()).then(|| number.to_string())
+                });
+                Problem::new(op, cells)
+            })
+            .collect()
+    }
+
+    /// Part 2: each column of a block is a number, digits read top to bottom,
+    /// columns read right to left.
+    pub fn column_wise(&self) -> Vec<Problem> {
+        self.blocks
+            .iter()
+            .zip(self.ops.iter())
+            .map(|(block, op)| {
+                let cells = block.clone().rev().map(|col| {
+                    let number: String = self.rows.iter().map(|row| row[col]).collect();
+                    number.trim().to_string()
+                });
+                Problem::new(op, cells)
+            })
+            .collect()
+    }
+
+    pub fn problems(&self, reading: Reading) -> Vec<Problem> {
+        match reading {
+            Reading::Rows => self.row_wise(),
+            Reading::Columns => self.column_wise(),
+        }
+    }
+
+    /// Text of a block, number rows then operator row.
+    fn block_text(&self, block: &Range<usize>) -> Vec<String> {
+        self.rows
+            .iter()
+            .chain(std::iter::once(&self.ops_row))
+            .map(|row| row[block.clone()].iter().collect())
+            .collect()
+    }
+
+    pub fn explain(&self, reading: Reading, registry: &Registry) -> Vec<Explanation> {
+        self.blocks
+            .iter()
+            .zip(self.problems(reading))
+            .enumerate()
+            .map(|(index, (block, problem))| {
+                let result = problem.solve(registry);
+                Explanation {
+                    index,
+                    columns: (block.start, block.end),
+                    text: self.block_text(block),
+                    result: result.as_ref().ok().copied(),
+                    error: result.err().map(|error| error.to_string()),
+                    op: problem.op,
+                    numbers: problem.numbers,
+                }
+            })
+            .collect()
+    }
+
+    /// Whole worksheet with every other block on a coloured background.
+    pub fn highlighted(&self) -> String {
+        const COLORS: [&str; 2] = ["\x1b[44m", "\x1b[45m"];
+        const RESET: &str = "\x1b[0m";
+
+        let mut output = String::new();
+        for row in self.rows.iter().chain(std::iter::once(&self.ops_row)) {
+            for (col, c) in row.iter().enumerate() {
+                match self.blocks.iter().position(|block| block.contains(&col)) {
+                    Some(idx) => output.push_str(&format!("{}{}{}", COLORS[idx % 2], c, RESET)),
+                    None => output.push(*c),
+                }
+            }
+            output.push('\n');
+        }
+        output
+    }
+}
+
+/// Problem index and the reason it could not be evaluated.
+type ProblemErrors = Vec<(usize, EvalError)>;
+
+/// Sum of all the problems, with checked `usize` arithmetic.
+fn solve(problems: &[Problem], registry: &Registry) -> Result<usize, ProblemErrors> {
+    let mut total = 0usize;
+    let mut errors = Vec::new();
+    for (idx, problem) in problems.iter().enumerate() {
+        match problem
+            .solve(registry)
+            .and_then(|value| total.checked_add(value).ok_or(EvalError::Overflow))
+        {
+            Ok(value) => total = value,
+            Err(error) => errors.push((idx, error)),
+        }
+    }
+    if errors.is_empty() {
+        Ok(total)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Sum of all the problems, in arbitrary precision.
+fn solve_big(problems: &[Problem], registry: &Registry) -> Result<BigInt, ProblemErrors> {
+    let mut total = BigInt::zero();
+    let mut errors = Vec::new();
+    for (idx, problem) in problems.iter().enumerate() {
+        match problem.solve_big(registry) {
+            Ok(value) => total += value,
+            Err(error) => errors.push((idx, error)),
+        }
+    }
+    if errors.is_empty() {
+        Ok(total)
+    } else {
+        Err(errors)
+    }
+}
+
+fn print_total<T: fmt::Display>(label: &str, total: Result<T, ProblemErrors>) {
+    match total {
+        Ok(total) => println!("{}: {}", label, total),
+        Err(errors) => {
+            for (idx, error) in errors {
+                eprintln!("{}: problem {}: {}", label, idx, error);
+            }
+        }
+    }
+}
+
+fn run(input: String, tab_width: usize) -> Result<usize, ProblemErrors> {
+    let worksheet = Worksheet::parse_with_tab_width(&input, tab_width);
+    dbg!(solve(&worksheet.column_wise(), &Registry::default()))
 }
 
 fn main() {
     let input = read_input(None);
 
-    let answer = run(input);
+    let args: Vec<String> = env::args().collect();
+    let tab_width = args
+        .iter()
+        .position(|arg| arg == "--tab-width")
+        .and_then(|pos| args.get(pos + 1))
+        .map_or(DEFAULT_TAB_WIDTH, |width| {
+            width.parse().expect("Invalid tab width")
+        });
+    let worksheet = Worksheet::parse_with_tab_width(&input, tab_width);
+    for warning in worksheet.warnings.iter() {
+        eprintln!("warning: {}", warning);
+    }
+    let registry = Registry::default();
+    if let Some(pos) = args.iter().position(|arg| arg == "--explain") {
+        // `--explain [rows|columns] [--json]`
+        let reading = match args.get(pos + 1).map(String::as_str) {
+            Some("rows") => Reading::Rows,
+            _ => Reading::Columns,
+        };
+        let explanations = worksheet.explain(reading, &registry);
+        if args.iter().any(|arg| arg == "--json") {
+            println!("{}", serde_json::to_string_pretty(&explanations).unwrap());
+        } else {
+            print!("{}", worksheet.highlighted());
+            for explanation in explanations.iter() {
+                print!("{}", explanation);
+            }
+        }
+        return;
+    }
+    if args.iter().any(|arg| arg == "--big") {
+        print_total("Answer part 1", solve_big(&worksheet.row_wise(), &registry));
+        print_total("Answer", solve_big(&worksheet.column_wise(), &registry));
+        return;
+    }
+    print_total("Answer part 1", solve(&worksheet.row_wise(), &registry));
 
-    println!("Answer: {}", answer);
+    print_total("Answer", run(input, tab_width));
 }
 
 #[allow(unused_imports)]
@@ -103,7 +637,220 @@ mod tests {
             "
         )));
         dbg!(&input);
-        let answer = run(input);
-        assert_eq!(answer, 3263827);
+        let answer = run(input, DEFAULT_TAB_WIDTH);
+        assert_eq!(answer, Ok(3263827));
+
+        // Read column-wise this is 8 / 0
+        let answer = run("08\n/\n".to_string(), DEFAULT_TAB_WIDTH);
+        assert_eq!(answer, Err(vec![(0, EvalError::DivisionByZero)]));
+    }
+
+    #[test]
+    fn test_worksheet() {
+        let worksheet = Worksheet::parse(indoc!(
+            r"
+            123 328  51 64
+             45 64  387 23
+              6 98  215 314
+            *   +   *   +
+            "
+        ));
+        assert_eq!(worksheet.blocks, vec![0..3, 4..7, 8..11, 12..15]);
+
+        let row_wise = worksheet.row_wise();
+        assert_eq!(row_wise[0].numbers, vec![123, 45, 6]);
+        assert_eq!(solve(&row_wise, &Registry::default()), Ok(4277556));
+
+        let column_wise = worksheet.column_wise();
+        assert_eq!(column_wise[3].numbers, vec![4, 431, 623]);
+        assert_eq!(solve(&column_wise, &Registry::default()), Ok(3263827));
+    }
+
+    #[test]
+    fn test_operators() {
+        let worksheet = Worksheet::parse(indoc!(
+            r"
+            20 100 3   7   2 9 1
+             5  10 9   1   3 0 1
+             2   5 4   8   2 2 1
+            -  /   min max ^ / %
+            "
+        ));
+        let registry = Registry::default();
+        let results: Vec<Result<usize, EvalError>> = worksheet
+            .row_wise()
+            .iter()
+            .map(|problem| problem.solve(&registry))
+            .collect();
+        assert_eq!(
+            results,
+            vec![
+                Ok(13),
+                Ok(2),
+                Ok(3),
+                Ok(8),
+                Ok(64),
+                Err(EvalError::DivisionByZero),
+                Err(EvalError::UnknownOperator("%".to_string())),
+            ]
+        );
+        let errors = solve(&worksheet.row_wise(), &registry).unwrap_err();
+        assert_eq!(
+            errors.iter().map(|(idx, _)| *idx).collect::<Vec<_>>(),
+            vec![5, 6]
+        );
+
+        // Cells that are not numbers fail their problem only
+        let worksheet = Worksheet::parse("1x  7\n 2  8\n+   *\n");
+        assert_eq!(
+            solve(&worksheet.row_wise(), &registry),
+            Err(vec![(0, EvalError::InvalidNumber("1x".to_string()))])
+        );
+        assert_eq!(
+            solve(&worksheet.column_wise(), &registry),
+            Err(vec![(0, EvalError::InvalidNumber("x2".to_string()))])
+        );
+        // A column of 21 digits does not fit in a usize
+        let worksheet = Worksheet::parse(&("9\n".repeat(21) + "+\n"));
+        assert_eq!(solve(&worksheet.row_wise(), &registry), Ok(189));
+        assert_eq!(
+            solve_big(&worksheet.column_wise(), &registry),
+            Err(vec![(0, EvalError::InvalidNumber("9".repeat(21)))])
+        );
+
+        let problem = Problem {
+            op: "*".to_string(),
+            numbers: vec![0, usize::MAX, 2],
+            invalid: None,
+        };
+        assert_eq!(problem.solve(&registry), Ok(0));
+        let problem = Problem {
+            op: "*".to_string(),
+            numbers: vec![usize::MAX, 2],
+            invalid: None,
+        };
+        assert_eq!(problem.solve(&registry), Err(EvalError::Overflow));
+        assert_eq!(
+            problem.solve_big(&registry),
+            Ok(BigInt::from(usize::MAX) * 2)
+        );
+        let problem = Problem {
+            op: "-".to_string(),
+            numbers: vec![1, 2],
+            invalid: None,
+        };
+        assert_eq!(problem.solve(&registry), Err(EvalError::Overflow));
+        assert_eq!(problem.solve_big(&registry), Ok(BigInt::from(-1)));
+    }
+
+    #[test]
+    fn test_explain() {
+        let worksheet = Worksheet::parse(indoc!(
+            r"
+            123 328
+             45 64
+              6 98
+            *   -
+            "
+        ));
+        let explanations = worksheet.explain(Reading::Columns, &Registry::default());
+        assert_eq!(explanations[0].numbers, vec![356, 24, 1]);
+        assert_eq!(explanations[0].result, Some(8544));
+        assert_eq!(explanations[1].numbers, vec![8, 248, 369]);
+        assert_eq!(explanations[1].text, vec!["328", "64 ", "98 ", "-  "]);
+        assert_eq!(
+            explanations[1].error,
+            Some("arithmetic overflow".to_string())
+        );
+        assert_eq!(
+            explanations[0].to_string(),
+            indoc!(
+                "
+                Problem 0 (columns 0..3): *
+                  | 123
+                  |  45
+                  |   6
+                  | *  
+                  numbers: 356 * 24 * 1
+                  result: 8544
+                "
+            )
+        );
+
+        let json = serde_json::to_value(&explanations).unwrap();
+        assert_eq!(json[0]["columns"], serde_json::json!([0, 3]));
+        assert_eq!(json[1]["result"], serde_json::Value::Null);
+
+        let highlighted = worksheet.highlighted();
+        assert_eq!(highlighted.lines().count(), 4);
+        assert_eq!(highlighted.matches("\x1b[44m").count(), 12);
+    }
+
+    #[test]
+    fn test_normalise() {
+        assert_eq!(
+            normalise("1\t2\r\n\t3\r\n", 4),
+            vec![
+                "1   2".chars().collect::<Vec<_>>(),
+                "    3".chars().collect::<Vec<_>>(),
+            ]
+        );
+        assert_eq!(
+            normalise("1\t2\n", 0),
+            vec!["1 2".chars().collect::<Vec<_>>()]
+        );
+
+        // Tabs, CRLF and an operator row shorter than the number rows
+        let worksheet = Worksheet::parse_with_tab_width("12\t34\r\n3\t4\r\n*\t+\r\n", 4);
+        assert_eq!(worksheet.blocks, vec![0..2, 4..6]);
+        assert_eq!(worksheet.warnings, vec![]);
+        assert_eq!(
+            solve(&worksheet.row_wise(), &Registry::default()),
+            Ok(36 + 38)
+        );
+        assert_eq!(
+            run("12\t34\n3\t4\n*\t+\n".to_string(), 4),
+            Ok(2 * 13 + 4 + 34)
+        );
+
+        // A ragged row that stops before the second block
+        let worksheet = Worksheet::parse("12  34\n3\n*   +\n");
+        let row_wise = worksheet.row_wise();
+        assert_eq!(row_wise[0].numbers, vec![12, 3]);
+        assert_eq!(row_wise[1].numbers, vec![34]);
+        assert_eq!(solve(&row_wise, &Registry::default()), Ok(36 + 34));
+
+        let worksheet = Worksheet::parse(indoc!(
+            r"
+            12  34  5
+             3   4  6
+            *  + -  +*
+            "
+        ));
+        assert_eq!(worksheet.ops, vec!["*", "-", "+*"]);
+        assert_eq!(
+            worksheet
+                .warnings
+                .iter()
+                .map(|warning| warning.to_string())
+                .collect::<Vec<_>>(),
+            vec!["operator + at column 3 is not under a problem"]
+        );
+
+        let worksheet = Worksheet::parse(indoc!(
+            r"
+            12  34
+             3   4
+            *+    
+            "
+        ));
+        assert_eq!(worksheet.ops, vec!["*+", ""]);
+        assert_eq!(
+            worksheet.warnings,
+            vec![Warning::MissingOperator {
+                block: 1,
+                columns: 4..6
+            }]
+        );
     }
 }
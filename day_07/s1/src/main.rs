@@ -1,6 +1,8 @@
 use std::{
     collections::{HashMap, HashSet, VecDeque},
+    env, fs, io,
     ops::Add,
+    path::Path,
 };
 
 fn read_input(input: Option<&str>) -> String {
@@ -39,13 +41,15 @@ impl Add for Coord {
 }
 
 #[allow(unused)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Grid {
     data: HashMap<Coord, char>,
     rows: usize,
     cols: usize,
     start: Coord,
     end: Coord,
+    /// Every `S` cell in reading order, `start` is the first one.
+    sources: Vec<Coord>,
 }
 
 impl Grid {
@@ -131,7 +135,7 @@ impl From<&str> for Grid {
 
 fn from_vec_of_vec(value: Vec<Vec<char>>) -> Grid {
     let mut data = HashMap::new();
-    let mut start = Coord::default();
+    let mut sources = Vec::new();
     let mut end = Coord::default();
     // Y
     let rows = value.len();
@@ -142,7 +146,7 @@ fn from_vec_of_vec(value: Vec<Vec<char>>) -> Grid {
         for (x, c) in row.iter().enumerate() {
             data.insert(Coord::new(x as isize, y as isize), *c);
             if *c == 'S' {
-                start = Coord::new(x as isize, y as isize);
+                sources.push(Coord::new(x as isize, y as isize));
             } else if *c == 'E' {
                 end = Coord::new(x as isize, y as isize);
             }
@@ -153,21 +157,197 @@ fn from_vec_of_vec(value: Vec<Vec<char>>) -> Grid {
         data,
         rows,
         cols,
-        start,
+        start: sources.first().copied().unwrap_or_default(),
         end,
+        sources,
     }
 }
 
+/// Character to colour mapping used by the image exports.
+#[derive(Debug, Clone)]
+pub struct Palette {
+    colors: Vec<(char, [u8; 3])>,
+    /// Colour used for characters missing from the palette.
+    fallback: [u8; 3],
+}
+
+impl Palette {
+    pub fn new(colors: &[(char, [u8; 3])], fallback: [u8; 3]) -> Palette {
+        Palette {
+            colors: colors.to_vec(),
+            fallback,
+        }
+    }
+
+    /// Index of a character in the palette, the fallback colour comes last.
+    fn index(&self, c: char) -> usize {
+        self.colors
+            .iter()
+            .position(|(key, _)| *key == c)
+            .unwrap_or(self.colors.len())
+    }
+
+    fn color(&self, c: char) -> [u8; 3] {
+        self.colors
+            .get(self.index(c))
+            .map_or(self.fallback, |(_, color)| *color)
+    }
+
+    /// Flat `[r, g, b, ...]` table, as expected by the GIF encoder.
+    fn rgb_table(&self) -> Vec<u8> {
+        self.colors
+            .iter()
+            .map(|(_, color)| *color)
+            .chain(std::iter::once(self.fallback))
+            .flatten()
+            .collect()
+    }
+}
+
+impl Grid {
+    /// Palette indexes, one per pixel, each cell being `scale` x `scale` pixels.
+    fn pixels(&self, palette: &Palette, scale: usize) -> Vec<usize> {
+        let mut pixels = Vec::with_capacity(self.rows * self.cols * scale * scale);
+        for y in 0..self.rows * scale {
+            for x in 0..self.cols * scale {
+                let c = self.data[&Coord::new((x / scale) as isize, (y / scale) as isize)];
+                pixels.push(palette.index(c));
+            }
+        }
+        pixels
+    }
+
+    pub fn to_png(&self, palette: &Palette, scale: usize) -> io::Result<Vec<u8>> {
+        let dimension = |cells: usize| {
+            cells
+                .checked_mul(scale)
+                .and_then(|pixels| u32::try_from(pixels).ok())
+                .ok_or_else(|| io::Error::other("Image too large for PNG"))
+        };
+        let (width, height) = (dimension(self.cols)?, dimension(self.rows)?);
+        let table = palette.rgb_table();
+        let data: Vec<u8> = self
+            .pixels(palette, scale)
+            .iter()
+            .flat_map(|i| table[i * 3..i * 3 + 3].to_vec())
+            .collect();
+
+        let mut output = Vec::new();
+        let mut encoder = png::Encoder::new(&mut output, width, height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&data)?;
+        writer.finish()?;
+        Ok(output)
+    }
+
+    pub fn to_svg(&self, palette: &Palette, scale: usize) -> io::Result<String> {
+        let dimension = |cells: usize| {
+            cells
+                .checked_mul(scale)
+                .ok_or_else(|| io::Error::other("Image too large for SVG"))
+        };
+        let (width, height) = (dimension(self.cols)?, dimension(self.rows)?);
+        let mut output = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}" shape-rendering="crispEdges">"#
+        );
+        output.push('\n');
+        for y in 0..self.rows {
+            for x in 0..self.cols {
+                let [r, g, b] = palette.color(self.data[&Coord::new(x as isize, y as isize)]);
+                output.push_str(&format!(
+                    r##"<rect x="{}" y="{}" width="{scale}" height="{scale}" fill="#{r:02x}{g:02x}{b:02x}"/>"##,
+                    x * scale,
+                    y * scale,
+                ));
+                output.push('\n');
+            }
+        }
+        output.push_str("</svg>\n");
+        Ok(output)
+    }
+}
+
+/// Stitch simulation steps into an animated GIF, `delay` is in 1/100 s.
+pub fn frames_to_gif(
+    frames: &[Grid],
+    palette: &Palette,
+    scale: usize,
+    delay: u16,
+) -> io::Result<Vec<u8>> {
+    let Some(first) = frames.first() else {
+        return Err(io::Error::other("No frame to encode"));
+    };
+    let dimension = |cells: usize| {
+        cells
+            .checked_mul(scale)
+            .and_then(|pixels| u16::try_from(pixels).ok())
+            .ok_or_else(|| io::Error::other("Image too large for GIF"))
+    };
+    let (width, height) = (dimension(first.cols)?, dimension(first.rows)?);
+    let mut output = Vec::new();
+    {
+        let mut encoder = gif::Encoder::new(&mut output, width, height, &palette.rgb_table())
+            .map_err(io::Error::other)?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(io::Error::other)?;
+        for grid in frames {
+            let buffer: Vec<u8> = grid
+                .pixels(palette, scale)
+                .iter()
+                .map(|i| {
+                    u8::try_from(*i).map_err(|_| io::Error::other("Palette too large for GIF"))
+                })
+                .collect::<io::Result<_>>()?;
+            let frame = gif::Frame {
+                width,
+                height,
+                delay,
+                buffer: buffer.into(),
+                ..Default::default()
+            };
+            encoder.write_frame(&frame).map_err(io::Error::other)?;
+        }
+    }
+    Ok(output)
+}
+
+/// Write one PNG and one SVG per step plus an animated GIF into `dir`.
+fn export_frames(dir: &Path, frames: &[Grid], palette: &Palette) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    for (step, grid) in frames.iter().enumerate() {
+        fs::write(
+            dir.join(format!("step_{step:04}.png")),
+            grid.to_png(palette, 8)?,
+        )?;
+        fs::write(
+            dir.join(format!("step_{step:04}.svg")),
+            grid.to_svg(palette, 8)?,
+        )?;
+    }
+    fs::write(
+        dir.join("steps.gif"),
+        frames_to_gif(frames, palette, 8, 20)?,
+    )?;
+    Ok(())
+}
+
 fn run(input: String) -> usize {
+    run_with_frames(input).0
+}
+
+/// Same as `run`, also returning a snapshot of the grid each time the beams reach a new row.
+fn run_with_frames(input: String) -> (usize, Vec<Grid>) {
     let mut grid = Grid::from(input.as_str());
     grid.print();
 
-    let mut beam_position = Coord::new(grid.start.x, grid.start.y);
+    let mut beam_position;
     let mut seen: HashSet<Coord> = HashSet::new();
     let mut splits = 0;
-    let mut beams = VecDeque::new();
-
-    beams.push_back(beam_position);
+    let mut beams: VecDeque<Coord> = grid.sources.iter().copied().collect();
+    let mut frames = vec![grid.clone()];
 
     while !beams.is_empty() {
         beam_position = beams.pop_front().unwrap();
@@ -180,6 +360,10 @@ fn run(input: String) -> usize {
             continue;
         }
 
+        if beam_position.y > frames.len() as isize - 1 + grid.start.y {
+            frames.push(grid.clone());
+        }
+
         if grid.is_splitter(&beam_position) {
             splits += 1;
             let mut new_pos = grid.split(&beam_position);
@@ -197,14 +381,32 @@ fn run(input: String) -> usize {
         grid.print();
         dbg!(splits);
     }
+    frames.push(grid.clone());
 
-    splits
+    (splits, frames)
 }
 
 fn main() {
     let input = read_input(None);
 
-    let answer = run(input);
+    let args: Vec<String> = env::args().collect();
+    let answer = if let Some(pos) = args.iter().position(|arg| arg == "--export") {
+        let dir = args.get(pos + 1).expect("Missing export directory");
+        let (splits, frames) = run_with_frames(input);
+        let palette = Palette::new(
+            &[
+                ('.', [20, 20, 30]),
+                ('S', [60, 200, 60]),
+                ('^', [160, 160, 160]),
+                ('|', [250, 220, 60]),
+            ],
+            [255, 0, 255],
+        );
+        export_frames(Path::new(dir), &frames, &palette).expect("Export failed");
+        splits
+    } else {
+        run(input)
+    };
 
     println!("Answer: {}", answer);
 }
@@ -248,4 +450,54 @@ mod tests {
         let answer = run(input);
         assert_eq!(answer, 21);
     }
+
+    #[test]
+    fn test_image_export() {
+        let input = indoc!(
+            r"
+            ..S..
+            .....
+            ..^..
+            .....
+            "
+        );
+        let (splits, frames) = run_with_frames(input.to_string());
+        assert_eq!(splits, 1);
+        assert_eq!(frames.len(), 5);
+        let palette = Palette::new(&[('.', [0, 0, 0]), ('|', [255, 255, 0])], [0, 0, 255]);
+
+        let last = frames.last().unwrap();
+        let svg = last.to_svg(&palette, 4).unwrap();
+        assert_eq!(svg.matches("<rect").count(), 20);
+        assert_eq!(svg.matches("#ffff00").count(), 5);
+
+        let png = last.to_png(&palette, 4).unwrap();
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+
+        let gif = frames_to_gif(&frames, &palette, 4, 10).unwrap();
+        assert!(gif.starts_with(b"GIF89a"));
+
+        // Frame sizes and palette indexes that do not fit the image formats
+        assert!(last.to_png(&palette, 1 << 31).is_err());
+        assert!(last.to_svg(&palette, usize::MAX).is_err());
+        assert!(frames_to_gif(&frames, &palette, 1 << 14, 10).is_err());
+        let colors: Vec<(char, [u8; 3])> = ('\u{100}'..'\u{22c}').map(|c| (c, [0, 0, 0])).collect();
+        let large = Palette::new(&colors, [255, 255, 255]);
+        assert!(frames_to_gif(&frames, &large, 1, 10).is_err());
+    }
+
+    #[test]
+    fn test_sources() {
+        let input = indoc!(
+            r"
+            .S...S.
+            .......
+            .^...^.
+            .......
+            ....^..
+            .......
+            "
+        );
+        assert_eq!(run(input.to_string()), 3);
+    }
 }
@@ -1,8 +1,13 @@
 use std::{
-    collections::{HashMap, HashSet, VecDeque},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    env, fmt,
+    io::{self, BufRead},
     ops::Add,
 };
 
+use num_bigint::BigUint;
+use rand::Rng;
+
 fn read_input(input: Option<&str>) -> String {
     let input = match input {
         None => include_str!("../../input.txt"),
@@ -46,6 +51,8 @@ pub struct Grid {
     cols: usize,
     start: Coord,
     end: Coord,
+    /// Every `S` cell in reading order with its weight, `start` is the first one.
+    sources: Vec<(Coord, u64)>,
 }
 
 impl Grid {
@@ -53,13 +60,13 @@ impl Grid {
         self.data.get(coord)
     }
 
-    fn set(&mut self, coord: &Coord, value: char) {
-        let cell = self.data.get_mut(coord).unwrap();
-        *cell = value;
-    }
-
-    fn is_splitter(&self, coord: &Coord) -> bool {
-        self.get(coord).is_some_and(|c| "^".contains(*c))
+    pub fn set_weight(&mut self, source: &Coord, weight: u64) {
+        let (_, w) = self
+            .sources
+            .iter_mut()
+            .find(|(pos, _)| pos == source)
+            .expect("Not a source");
+        *w = weight;
     }
 
     // fn neighbors(&self, coord: &Coord) -> Vec<Coord> {
@@ -89,24 +96,6 @@ impl Grid {
     //     neighbors
     // }
 
-    fn down(&self, coord: &Coord) -> Option<Coord> {
-        let down = *coord + Coord::new(0, 1);
-        self.get(&down)?;
-        Some(down)
-    }
-
-    fn split(&self, coord: &Coord) -> VecDeque<Coord> {
-        let mut split_positions: VecDeque<Coord> = VecDeque::new();
-        if self.get(&(*coord + Coord::new(1, 0))).is_some() {
-            split_positions.push_front(*coord + Coord::new(1, 0));
-        }
-        if self.get(&(*coord + Coord::new(-1, 0))).is_some() {
-            split_positions.push_front(*coord + Coord::new(-1, 0));
-        }
-
-        split_positions
-    }
-
     pub fn print(&self) {
         for y in 0..self.rows {
             for x in 0..self.cols {
@@ -131,7 +120,7 @@ impl From<&str> for Grid {
 
 fn from_vec_of_vec(value: Vec<Vec<char>>) -> Grid {
     let mut data = HashMap::new();
-    let mut start = Coord::default();
+    let mut sources = Vec::new();
     let mut end = Coord::default();
     // Y
     let rows = value.len();
@@ -142,7 +131,7 @@ fn from_vec_of_vec(value: Vec<Vec<char>>) -> Grid {
         for (x, c) in row.iter().enumerate() {
             data.insert(Coord::new(x as isize, y as isize), *c);
             if *c == 'S' {
-                start = Coord::new(x as isize, y as isize);
+                sources.push((Coord::new(x as isize, y as isize), 1));
             } else if *c == 'E' {
                 end = Coord::new(x as isize, y as isize);
             }
@@ -153,54 +142,813 @@ fn from_vec_of_vec(value: Vec<Vec<char>>) -> Grid {
         data,
         rows,
         cols,
-        start,
+        start: sources.first().map(|(pos, _)| *pos).unwrap_or_default(),
         end,
+        sources,
+    }
+}
+
+#[derive(Debug, Eq, Hash, PartialEq, Clone, Copy)]
+pub enum Direction {
+    Up,
+    Right,
+    Down,
+    Left,
+}
+
+impl Direction {
+    fn delta(&self) -> Coord {
+        match self {
+            Direction::Up => Coord::new(0, -1),
+            Direction::Right => Coord::new(1, 0),
+            Direction::Down => Coord::new(0, 1),
+            Direction::Left => Coord::new(-1, 0),
+        }
+    }
+
+    fn is_vertical(&self) -> bool {
+        matches!(self, Direction::Up | Direction::Down)
+    }
+
+    /// Offsets of the two cells beside a beam, west / north side first.
+    fn sides(&self) -> [Coord; 2] {
+        if self.is_vertical() {
+            [Coord::new(-1, 0), Coord::new(1, 0)]
+        } else {
+            [Coord::new(0, -1), Coord::new(0, 1)]
+        }
+    }
+
+    /// Reflection on a `/` mirror.
+    fn slash(&self) -> Direction {
+        match self {
+            Direction::Up => Direction::Right,
+            Direction::Right => Direction::Up,
+            Direction::Down => Direction::Left,
+            Direction::Left => Direction::Down,
+        }
+    }
+
+    /// Reflection on a `\` mirror.
+    fn backslash(&self) -> Direction {
+        match self {
+            Direction::Up => Direction::Left,
+            Direction::Left => Direction::Up,
+            Direction::Down => Direction::Right,
+            Direction::Right => Direction::Down,
+        }
+    }
+}
+
+/// A beam standing in a cell, travelling in a direction.
+#[derive(Debug, Eq, Hash, PartialEq, Clone, Copy)]
+pub struct Beam {
+    pos: Coord,
+    dir: Direction,
+}
+
+/// What happens to a beam moving to its next cell.
+#[derive(Debug, PartialEq)]
+enum Step {
+    /// The beam leaves the manifold, ending a timeline.
+    Exit,
+    /// The beam is stopped by an absorber, ending a timeline.
+    Absorbed,
+    /// The beam continues as these beams. `split` is the splitter cell if it was deflected.
+    Beams {
+        beams: Vec<Beam>,
+        split: Option<Coord>,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TimelineError {
+    /// A beam can come back to this state, so there are infinitely many timelines.
+    Loop(Beam),
+    /// The counter type cannot hold the number of timelines.
+    Overflow,
+    /// Device the row sweep cannot handle, it needs the full simulation.
+    UnsupportedDevice { device: char, pos: Coord },
+}
+
+impl fmt::Display for TimelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimelineError::Loop(beam) => write!(
+                f,
+                "beam loops forever at ({}, {}) going {:?}",
+                beam.pos.x, beam.pos.y, beam.dir
+            ),
+            TimelineError::Overflow => write!(f, "timeline count overflow"),
+            TimelineError::UnsupportedDevice { device, pos } => write!(
+                f,
+                "device {} at ({}, {}) needs the full simulation",
+                device, pos.x, pos.y
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TimelineError {}
+
+/// Number type used to count timelines.
+/// `zero` is taken from a value so that settings like a modulus are carried over.
+pub trait Counter: Clone {
+    fn zero(&self) -> Self;
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    /// Multiply by a source weight.
+    fn scale(&self, weight: u64) -> Option<Self>;
+}
+
+macro_rules! impl_counter {
+    ($($t:ty),*) => {
+        $(
+            impl Counter for $t {
+                fn zero(&self) -> Self {
+                    0
+                }
+
+                fn checked_add(&self, other: &Self) -> Option<Self> {
+                    <$t>::checked_add(*self, *other)
+                }
+
+                fn scale(&self, weight: u64) -> Option<Self> {
+                    <$t>::checked_mul(*self, weight.try_into().ok()?)
+                }
+            }
+        )*
+    };
+}
+
+impl_counter!(usize, u64, u128);
+
+/// No count at all, only checks that the timelines are finite.
+impl Counter for () {
+    fn zero(&self) -> Self {}
+
+    fn checked_add(&self, _other: &Self) -> Option<Self> {
+        Some(())
+    }
+
+    fn scale(&self, _weight: u64) -> Option<Self> {
+        Some(())
+    }
+}
+
+impl Counter for BigUint {
+    fn zero(&self) -> Self {
+        BigUint::ZERO
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn scale(&self, weight: u64) -> Option<Self> {
+        Some(self * weight)
+    }
+}
+
+/// Count modulo a user-chosen modulus, never overflows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Modular {
+    value: u64,
+    modulus: u64,
+}
+
+impl Modular {
+    pub fn new(value: u64, modulus: u64) -> Modular {
+        assert!(modulus > 0, "modulus must be positive");
+        Modular {
+            value: value % modulus,
+            modulus,
+        }
+    }
+}
+
+impl fmt::Display for Modular {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (mod {})", self.value, self.modulus)
     }
 }
 
-fn run(input: String) -> usize {
-    let grid = Grid::from(input.as_str());
+impl Counter for Modular {
+    fn zero(&self) -> Self {
+        Modular::new(0, self.modulus)
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        let sum = (self.value as u128 + other.value as u128) % self.modulus as u128;
+        Some(Modular::new(sum as u64, self.modulus))
+    }
+
+    fn scale(&self, weight: u64) -> Option<Self> {
+        let product = (self.value as u128 * weight as u128) % self.modulus as u128;
+        Some(Modular::new(product as u64, self.modulus))
+    }
+}
 
-    let mut seen: HashMap<Coord, usize> = HashMap::new();
+impl Grid {
+    /// Devices:
+    /// - `.` and `S`: the beam goes straight on.
+    /// - `^`: splitter, the beam continues from both cells beside the splitter.
+    /// - `<` / `>`: one-way splitter, vertical beams only continue from the
+    ///   west / east cell, horizontal beams go straight on.
+    /// - `/` and `\`: mirrors.
+    /// - `#`: absorber.
+    /// - `+`: merger, every beam leaves it travelling down.
+    ///
+    /// Side cells out of the grid are dropped, as the beam cannot go there.
+    fn step(&self, beam: &Beam) -> Step {
+        let next = beam.pos + beam.dir.delta();
+        let Some(device) = self.get(&next) else {
+            return Step::Exit;
+        };
 
-    // Iterate rows bottom → top !
-    for y in (0..grid.rows).rev() {
-        for x in 0..grid.cols {
-            let pos = Coord::new(x as isize, y as isize);
+        let [west, east] = beam.dir.sides();
+        let deflect = |sides: &[Coord]| Step::Beams {
+            beams: sides
+                .iter()
+                .map(|side| next + *side)
+                .filter(|side| self.get(side).is_some())
+                .map(|pos| Beam { pos, dir: beam.dir })
+                .collect(),
+            split: Some(next),
+        };
+        let go = |dir: Direction| Step::Beams {
+            beams: vec![Beam { pos: next, dir }],
+            split: None,
+        };
 
-            // First step is always "go down"
-            let Some(down) = grid.down(&pos) else {
-                // Falling out of grid => one finished timeline
-                seen.insert(pos, 1);
+        match device {
+            '^' => deflect(&[west, east]),
+            '<' if beam.dir.is_vertical() => deflect(&[west]),
+            '>' if beam.dir.is_vertical() => deflect(&[east]),
+            '/' => go(beam.dir.slash()),
+            '\\' => go(beam.dir.backslash()),
+            '#' => Step::Absorbed,
+            '+' => go(Direction::Down),
+            _ => go(beam.dir),
+        }
+    }
+
+    fn source_beams(&self) -> Vec<Beam> {
+        self.sources
+            .iter()
+            .map(|(pos, _)| Beam {
+                pos: *pos,
+                dir: Direction::Down,
+            })
+            .collect()
+    }
+
+    /// Part 1: number of distinct splitters hit by a beam.
+    fn splits(&self) -> usize {
+        let mut seen: HashSet<Beam> = HashSet::new();
+        let mut hits: HashSet<Coord> = HashSet::new();
+        let mut beams = VecDeque::from(self.source_beams());
+
+        while let Some(beam) = beams.pop_front() {
+            if let Step::Beams { beams: next, split } = self.step(&beam) {
+                if let Some(splitter) = split {
+                    hits.insert(splitter);
+                }
+                for next in next {
+                    if seen.insert(next) {
+                        beams.push_back(next);
+                    }
+                }
+            }
+        }
+
+        hits.len()
+    }
+
+    /// Part 2: number of timelines, i.e. distinct paths from a source to an exit
+    /// or an absorber, each source counting as many times as its weight.
+    /// Fails if a beam can loop forever.
+    fn timelines(&self) -> Result<usize, TimelineError> {
+        self.timelines_with(1)
+    }
+
+    /// Same as `timelines`, counting with `one`'s type.
+    fn timelines_with<C: Counter>(&self, one: C) -> Result<C, TimelineError> {
+        let count = self.timeline_counts(one.clone())?;
+        self.weighted_total(&count, one.zero())
+    }
+
+    /// Sum of the sources' timeline counts, each one multiplied by its weight.
+    fn weighted_total<C: Counter>(
+        &self,
+        count: &HashMap<Beam, C>,
+        zero: C,
+    ) -> Result<C, TimelineError> {
+        self.sources
+            .iter()
+            .zip(self.source_beams())
+            .try_fold(zero, |acc, ((_, weight), beam)| {
+                count[&beam]
+                    .scale(*weight)
+                    .and_then(|timelines| acc.checked_add(&timelines))
+            })
+            .ok_or(TimelineError::Overflow)
+    }
+
+    /// Every beam reachable from the sources, each one after the beams it leads to.
+    fn post_order(&self) -> Result<Vec<Beam>, TimelineError> {
+        let mut order = Vec::new();
+        let mut done: HashSet<Beam> = HashSet::new();
+        // Beams of the current path, waiting for their children to be done
+        let mut on_path: HashSet<Beam> = HashSet::new();
+        let mut stack: Vec<(Beam, bool)> = self
+            .source_beams()
+            .into_iter()
+            .rev()
+            .map(|beam| (beam, false))
+            .collect();
+
+        // Iterative DFS, paths can be too long for recursion
+        while let Some((beam, expanded)) = stack.pop() {
+            if done.contains(&beam) {
+                continue;
+            }
+            if expanded {
+                done.insert(beam);
+                on_path.remove(&beam);
+                order.push(beam);
                 continue;
+            }
+
+            if !on_path.insert(beam) {
+                return Err(TimelineError::Loop(beam));
+            }
+            stack.push((beam, true));
+            if let Step::Beams { beams, .. } = self.step(&beam) {
+                for next in beams {
+                    if on_path.contains(&next) {
+                        return Err(TimelineError::Loop(next));
+                    }
+                    stack.push((next, false));
+                }
+            }
+        }
+
+        Ok(order)
+    }
+
+    /// Number of timelines from every beam reachable from the sources.
+    fn timeline_counts<C: Counter>(&self, one: C) -> Result<HashMap<Beam, C>, TimelineError> {
+        let mut count: HashMap<Beam, C> = HashMap::new();
+        for beam in self.post_order()? {
+            let value = match self.step(&beam) {
+                Step::Exit | Step::Absorbed => one.clone(),
+                Step::Beams { beams, .. } => beams
+                    .iter()
+                    .try_fold(one.zero(), |acc, next| acc.checked_add(&count[next]))
+                    .ok_or(TimelineError::Overflow)?,
             };
+            count.insert(beam, value);
+        }
+
+        Ok(count)
+    }
 
-            let value = if grid.is_splitter(&down) {
-                // Splitter: ONLY left/right branches (no straight)
-                let mut total = 0usize;
-                for side in grid.split(&down) {
-                    total += *seen.get(&side).expect("Missing data for this position");
+    /// Weighted number of timelines leaving through each column of the bottom row,
+    /// split by source.
+    fn exit_histogram(&self) -> Result<BTreeMap<isize, Exit>, TimelineError> {
+        let order = self.post_order()?;
+        let mut histogram: BTreeMap<isize, Exit> = BTreeMap::new();
+
+        for ((source, weight), source_beam) in self.sources.iter().zip(self.source_beams()) {
+            // Push the multiplicities forward, parents before children
+            let mut multiplicity: HashMap<Beam, u128> =
+                HashMap::from([(source_beam, *weight as u128)]);
+            for beam in order.iter().rev() {
+                let Some(count) = multiplicity.get(beam).copied() else {
+                    continue;
+                };
+                match self.step(beam) {
+                    Step::Exit
+                        if beam.dir == Direction::Down && beam.pos.y == self.rows as isize - 1 =>
+                    {
+                        let exit = histogram.entry(beam.pos.x).or_default();
+                        exit.timelines = exit
+                            .timelines
+                            .checked_add(count)
+                            .ok_or(TimelineError::Overflow)?;
+                        *exit.by_source.entry(*source).or_default() += count;
+                    }
+                    Step::Beams { beams, .. } => {
+                        for next in beams {
+                            let entry = multiplicity.entry(next).or_default();
+                            *entry = entry.checked_add(count).ok_or(TimelineError::Overflow)?;
+                        }
+                    }
+                    _ => {}
                 }
-                total
-            } else {
-                // Normal cell: continue straight
-                *seen.get(&down).expect("Missing data for this position")
+            }
+        }
+
+        Ok(histogram)
+    }
+
+    /// Lazily enumerate every timeline, source by source and in lexicographic order
+    /// from each one. A source's timelines are repeated as many times as its weight.
+    fn paths(&self) -> Result<Paths<'_>, TimelineError> {
+        // Make sure the enumeration ends
+        self.timeline_counts(())?;
+        Ok(Paths {
+            grid: self,
+            sources: self
+                .source_beams()
+                .into_iter()
+                .zip(self.sources.iter().map(|(_, weight)| *weight))
+                .rev()
+                .collect(),
+            stack: Vec::new(),
+        })
+    }
+
+    /// The `k`-th timeline (0-based) in the order of `paths`, `None` if there are fewer.
+    fn nth_timeline(&self, k: u128) -> Result<Option<Timeline>, TimelineError> {
+        let count = self.timeline_counts(1u128)?;
+        Ok(self.nth_timeline_with(&count, k))
+    }
+
+    fn nth_timeline_with(&self, count: &HashMap<Beam, u128>, mut k: u128) -> Option<Timeline> {
+        // Find the source, each one owns `count × weight` consecutive timelines
+        for ((_, weight), beam) in self.sources.iter().zip(self.source_beams()) {
+            let timelines = count[&beam];
+            let block = timelines.saturating_mul(*weight as u128);
+            if k < block {
+                return Some(self.nth_timeline_from(count, beam, k % timelines));
+            }
+            k -= block;
+        }
+        None
+    }
+
+    /// The `k`-th timeline starting with `beam`, `k` must be lower than its count.
+    fn nth_timeline_from(
+        &self,
+        count: &HashMap<Beam, u128>,
+        mut beam: Beam,
+        mut k: u128,
+    ) -> Timeline {
+        let mut timeline = Vec::new();
+        loop {
+            match self.step(&beam) {
+                Step::Exit | Step::Absorbed => return timeline,
+                Step::Beams { beams, split } => match beams.as_slice() {
+                    [next] => beam = *next,
+                    [left, right] => {
+                        // Skip all the timelines going left if k is past them
+                        let (choice, next) = if k < count[left] {
+                            (Choice::Left, left)
+                        } else {
+                            k -= count[left];
+                            (Choice::Right, right)
+                        };
+                        timeline.push((split.unwrap(), choice));
+                        beam = *next;
+                    }
+                    _ => unreachable!("k is lower than the number of timelines"),
+                },
+            }
+        }
+    }
+
+    /// Sampler of uniformly random timelines among the ones counted by `timelines`.
+    fn sampler(&self) -> Result<Sampler<'_>, TimelineError> {
+        let count = self.timeline_counts(1u128)?;
+        let total = self.weighted_total(&count, 0)?;
+        Ok(Sampler {
+            grid: self,
+            count,
+            total,
+        })
+    }
+}
+
+/// Draws timelines with the counts computed once, see `Grid::sampler`.
+pub struct Sampler<'a> {
+    grid: &'a Grid,
+    count: HashMap<Beam, u128>,
+    total: u128,
+}
+
+impl Sampler<'_> {
+    /// Uniformly random timeline, `None` if there is none.
+    fn sample(&self, rng: &mut impl Rng) -> Option<Timeline> {
+        if self.total == 0 {
+            return None;
+        }
+        self.grid
+            .nth_timeline_with(&self.count, rng.random_range(0..self.total))
+    }
+}
+
+/// Timelines leaving through one bottom column.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Exit {
+    timelines: u128,
+    /// Timelines coming from each source.
+    by_source: HashMap<Coord, u128>,
+}
+
+/// Side taken at a splitter with two branches: west / north is `Left`.
+#[derive(Debug, Eq, Hash, PartialEq, Clone, Copy, PartialOrd, Ord)]
+pub enum Choice {
+    Left,
+    Right,
+}
+
+/// Choices made along a timeline, with the splitter where each one is made.
+type Timeline = Vec<(Coord, Choice)>;
+
+fn format_timeline(timeline: &Timeline) -> String {
+    timeline
+        .iter()
+        .map(|(splitter, choice)| {
+            let side = match choice {
+                Choice::Left => 'L',
+                Choice::Right => 'R',
             };
+            format!("({},{}){}", splitter.x, splitter.y, side)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Iterator over the timelines, depth first with `Left` before `Right`.
+pub struct Paths<'a> {
+    grid: &'a Grid,
+    /// Sources still to enumerate, last one first, with their remaining repetitions.
+    sources: Vec<(Beam, u64)>,
+    stack: Vec<(Beam, Timeline)>,
+}
+
+impl Iterator for Paths<'_> {
+    type Item = Timeline;
 
-            seen.insert(pos, value);
+    fn next(&mut self) -> Option<Timeline> {
+        loop {
+            while let Some((beam, timeline)) = self.stack.pop() {
+                match self.grid.step(&beam) {
+                    Step::Exit | Step::Absorbed => return Some(timeline),
+                    Step::Beams { beams, split } => match beams.as_slice() {
+                        [left, right] => {
+                            let splitter = split.unwrap();
+                            let mut right_timeline = timeline.clone();
+                            right_timeline.push((splitter, Choice::Right));
+                            self.stack.push((*right, right_timeline));
+                            let mut left_timeline = timeline;
+                            left_timeline.push((splitter, Choice::Left));
+                            self.stack.push((*left, left_timeline));
+                        }
+                        beams => {
+                            for next in beams {
+                                self.stack.push((*next, timeline.clone()));
+                            }
+                        }
+                    },
+                }
+            }
+
+            // Start the next repetition of the current source, or the next source
+            let (beam, repeats) = self.sources.last_mut()?;
+            if *repeats == 0 {
+                self.sources.pop();
+            } else {
+                *repeats -= 1;
+                self.stack.push((*beam, Vec::new()));
+            }
         }
     }
+}
 
-    // Return the value from the start position
-    seen[&grid.start]
+#[derive(Debug, PartialEq, Eq)]
+pub struct Sweep<C> {
+    splits: usize,
+    timelines: C,
+}
+
+/// Part 1 and part 2 in a single top-down pass, keeping only the beam multiplicity
+/// of each column. Lines can be streamed, only `.`, `S` and `^` are supported.
+fn sweep<C: Counter, L: AsRef<str>>(
+    lines: impl IntoIterator<Item = L>,
+    one: C,
+) -> Result<Sweep<C>, TimelineError> {
+    let zero = one.zero();
+    let mut beams: Vec<C> = Vec::new();
+    // Whether any beam is in the column, counts can be 0 with a modular counter
+    let mut lit: Vec<bool> = Vec::new();
+    let mut splits = 0;
+
+    for (y, line) in lines.into_iter().enumerate() {
+        let row: Vec<char> = line.as_ref().chars().collect();
+        if y == 0 {
+            beams = vec![zero.clone(); row.len()];
+            lit = vec![false; row.len()];
+        }
+        let width = beams.len();
+        let mut next_beams = vec![zero.clone(); width];
+        let mut next_lit = vec![false; width];
+        let mut add = |x: usize, count: &C| -> Result<(), TimelineError> {
+            next_beams[x] = next_beams[x]
+                .checked_add(count)
+                .ok_or(TimelineError::Overflow)?;
+            next_lit[x] = true;
+            Ok(())
+        };
+
+        for x in 0..width {
+            let device = row.get(x).copied().unwrap_or('.');
+            match device {
+                'S' => add(x, &one)?,
+                '^' if lit[x] => {
+                    splits += 1;
+                    // Side cells out of the grid are dropped
+                    if x > 0 {
+                        add(x - 1, &beams[x])?;
+                    }
+                    if x + 1 < width {
+                        add(x + 1, &beams[x])?;
+                    }
+                }
+                '.' | '^' => {}
+                device => {
+                    return Err(TimelineError::UnsupportedDevice {
+                        device,
+                        pos: Coord::new(x as isize, y as isize),
+                    });
+                }
+            }
+            if device != '^' && lit[x] {
+                add(x, &beams[x])?;
+            }
+        }
+
+        beams = next_beams;
+        lit = next_lit;
+    }
+
+    // Every remaining beam falls out of the bottom
+    let timelines = beams
+        .iter()
+        .try_fold(zero, |acc, count| acc.checked_add(count))
+        .ok_or(TimelineError::Overflow)?;
+
+    Ok(Sweep { splits, timelines })
+}
+
+fn run(input: String) -> Result<usize, TimelineError> {
+    match sweep(input.lines(), 1usize) {
+        Ok(result) => Ok(result.timelines),
+        Err(TimelineError::UnsupportedDevice { .. }) => Grid::from(input.as_str()).timelines(),
+        Err(error) => Err(error),
+    }
+}
+
+/// Evaluate `$count` with `$one` the unit of the counter named `$counter`:
+/// `u64`, `u128`, `big` or `mod:<modulus>`.
+macro_rules! with_counter {
+    ($counter:expr, |$one:ident| $count:expr) => {
+        match $counter.split_once(':') {
+            Some(("mod", modulus)) => {
+                let $one = Modular::new(1, modulus.parse().expect("Invalid modulus"));
+                $count
+            }
+            _ => match $counter {
+                "u64" => {
+                    let $one = 1u64;
+                    $count
+                }
+                "u128" => {
+                    let $one = 1u128;
+                    $count
+                }
+                "big" => {
+                    let $one = BigUint::from(1u8);
+                    $count
+                }
+                other => panic!("unknown counter {}", other),
+            },
+        }
+    };
 }
 
 fn main() {
+    let args: Vec<String> = env::args().collect();
+    // `--counter u64|u128|big|mod:<modulus>`
+    let counter = args
+        .iter()
+        .position(|arg| arg == "--counter")
+        .and_then(|pos| args.get(pos + 1))
+        .map(String::as_str);
+
+    if args.iter().any(|arg| arg == "--stream") {
+        let lines = io::stdin()
+            .lock()
+            .lines()
+            .map(|line| line.expect("Cannot read stdin"));
+        let result = with_counter!(counter.unwrap_or("u128"), |one| sweep(lines, one)
+            .map(|result| (result.splits, result.timelines.to_string())));
+        match result {
+            Ok((splits, timelines)) => {
+                println!("Answer part 1: {}", splits);
+                println!("Answer: {}", timelines);
+            }
+            Err(error) => eprintln!("Error: {}", error),
+        }
+        return;
+    }
+
     let input = read_input(None);
 
-    let answer = run(input);
+    let mut grid = Grid::from(input.as_str());
+    // `--weight <x>,<y>=<weight>`, can be repeated
+    for (pos, arg) in args.iter().enumerate() {
+        if arg == "--weight" {
+            let spec = args.get(pos + 1).expect("Missing weight");
+            let (coord, weight) = spec.split_once('=').expect("Invalid weight");
+            let (x, y) = coord.split_once(',').expect("Invalid source");
+            grid.set_weight(
+                &Coord::new(x.parse().expect("Invalid x"), y.parse().expect("Invalid y")),
+                weight.parse().expect("Invalid weight"),
+            );
+        }
+    }
+    println!("Answer part 1: {}", grid.splits());
+
+    if args.iter().any(|arg| arg == "--exits") {
+        let histogram = grid.exit_histogram().expect("Cannot count timelines");
+        for (column, exit) in histogram.iter() {
+            let mut sources: Vec<(&Coord, &u128)> = exit.by_source.iter().collect();
+            sources.sort_by_key(|(pos, _)| (pos.y, pos.x));
+            let sources: Vec<String> = sources
+                .iter()
+                .map(|(pos, count)| format!("({},{})={}", pos.x, pos.y, count))
+                .collect();
+            println!(
+                "column {}: {} [{}]",
+                column,
+                exit.timelines,
+                sources.join(" ")
+            );
+        }
+        return;
+    }
+
+    // `--list <n>`, `--nth <k>` or `--sample <n>` to inspect individual timelines
+    let option = |name: &str| -> Option<u128> {
+        args.iter()
+            .position(|arg| arg == name)
+            .and_then(|pos| args.get(pos + 1))
+            .map(|value| value.parse().expect("Invalid number"))
+    };
+    let timelines: Option<Vec<Timeline>> = if let Some(n) = option("--list") {
+        Some(
+            grid.paths()
+                .expect("Cannot enumerate timelines")
+                .take(n as usize)
+                .collect(),
+        )
+    } else if let Some(k) = option("--nth") {
+        Some(
+            grid.nth_timeline(k)
+                .expect("Cannot count timelines")
+                .into_iter()
+                .collect(),
+        )
+    } else {
+        option("--sample").map(|n| {
+            let sampler = grid.sampler().expect("Cannot count timelines");
+            let mut rng = rand::rng();
+            (0..n).filter_map(|_| sampler.sample(&mut rng)).collect()
+        })
+    };
+    if let Some(timelines) = timelines {
+        for timeline in timelines.iter() {
+            println!("{}", format_timeline(timeline));
+        }
+        return;
+    }
 
-    println!("Answer: {}", answer);
+    let answer = if let Some(counter) = counter {
+        with_counter!(counter, |one| grid
+            .timelines_with(one)
+            .map(|count| count.to_string()))
+    } else if grid.sources.iter().all(|(_, weight)| *weight == 1) {
+        run(input).map(|count| count.to_string())
+    } else {
+        grid.timelines().map(|count| count.to_string())
+    };
+
+    match answer {
+        Ok(answer) => println!("Answer: {}", answer),
+        Err(error) => eprintln!("Error: {}", error),
+    }
 }
 
 #[allow(unused_imports)]
@@ -210,6 +958,7 @@ mod tests {
     use super::*;
     use indoc::indoc;
     use pretty_assertions::{assert_eq, assert_ne};
+    use rand::SeedableRng;
 
     #[test]
     fn test_fake() {
@@ -240,6 +989,361 @@ mod tests {
         )));
         dbg!(&input);
         let answer = run(input);
-        assert_eq!(answer, 40);
+        assert_eq!(answer, Ok(40));
+    }
+
+    #[test]
+    fn test_splits() {
+        let grid = Grid::from(indoc!(
+            r"
+            .......S.......
+            ...............
+            .......^.......
+            ...............
+            ......^.^......
+            ...............
+            .....^.^.^.....
+            ...............
+            ....^.^...^....
+            ...............
+            ...^.^...^.^...
+            ...............
+            ..^...^.....^..
+            ...............
+            .^.^.^.^.^...^.
+            ...............
+            "
+        ));
+        assert_eq!(grid.splits(), 21);
+    }
+
+    #[test]
+    fn test_devices() {
+        // Mirrors send the beam around the absorber and the first splitter, then the
+        // merger turns it down onto the splitter at the bottom
+        let grid = Grid::from(indoc!(
+            r"
+            .S...
+            .\..\
+            .#...
+            .^.+/
+            ...^.
+            "
+        ));
+        assert_eq!(grid.splits(), 1);
+        assert_eq!(grid.timelines(), Ok(2));
+
+        let grid = Grid::from(indoc!(
+            r"
+            .S...
+            .\..\
+            .....
+            .....
+            "
+        ));
+        // Down, right along row 1, then down the right edge and out through the bottom
+        assert_eq!(grid.splits(), 0);
+        assert_eq!(grid.timelines(), Ok(1));
+
+        let grid = Grid::from(indoc!(
+            r"
+            ..S..
+            ..^..
+            .<.>.
+            #....
+            "
+        ));
+        assert_eq!(grid.splits(), 3);
+        // Left branch: absorbed; right branch: exits
+        assert_eq!(grid.timelines(), Ok(2));
+
+        // Mergers: each branch of the splitter goes through its own one, only the
+        // right branch then reaches the second splitter
+        let grid = Grid::from(indoc!(
+            r"
+            ..S..
+            ..^..
+            .+.+.
+            ...^.
+            "
+        ));
+        assert_eq!(grid.splits(), 2);
+        assert_eq!(grid.timelines(), Ok(3));
+
+        // The left branch is mirrored into the merger the right branch falls into:
+        // both leave it as the same beam, so the splitter below counts once for
+        // part 1 while each branch still splits there for part 2
+        let grid = Grid::from(indoc!(
+            r"
+            ..S..
+            ..^..
+            .\.+.
+            ...^.
+            "
+        ));
+        assert_eq!(grid.splits(), 2);
+        assert_eq!(grid.timelines(), Ok(4));
+
+        let grid = Grid::from(indoc!(
+            r"
+            .S..
+            .+.\
+            ....
+            .\./
+            "
+        ));
+        // The merger sends the beam back into a mirror loop
+        assert!(grid.timelines().is_err());
+        assert_eq!(grid.splits(), 0);
+    }
+
+    #[test]
+    fn test_counters() {
+        // Every splitter row doubles the number of timelines
+        let depth = 70;
+        let mut lines = vec![format!(
+            "{}S{}",
+            ".".repeat(depth + 1),
+            ".".repeat(depth + 1)
+        )];
+        for row in 0..depth {
+            let mut line = vec!['.'; 2 * depth + 3];
+            for x in (depth + 1 - row..=depth + 1 + row).step_by(2) {
+                line[x] = '^';
+            }
+            lines.push(line.iter().collect());
+            lines.push(".".repeat(2 * depth + 3));
+        }
+        let grid = Grid::from(lines.join("\n").as_str());
+
+        assert_eq!(grid.timelines_with(1u64), Err(TimelineError::Overflow));
+        assert_eq!(run(lines.join("\n")), Err(TimelineError::Overflow));
+        assert_eq!(grid.timelines_with(1u128), Ok(1u128 << depth));
+        assert_eq!(
+            grid.timelines_with(BigUint::from(1u8)),
+            Ok(BigUint::from(1u8) << depth)
+        );
+        let modulus = 1_000_000_007;
+        assert_eq!(
+            grid.timelines_with(Modular::new(1, modulus)),
+            Ok(Modular::new(
+                ((1u128 << depth) % modulus as u128) as u64,
+                modulus
+            ))
+        );
+    }
+
+    #[test]
+    fn test_sweep() {
+        let input = indoc!(
+            r"
+            .......S.......
+            ...............
+            .......^.......
+            ...............
+            ......^.^......
+            ...............
+            .....^.^.^.....
+            ...............
+            ....^.^...^....
+            ...............
+            ...^.^...^.^...
+            ...............
+            ..^...^.....^..
+            ...............
+            .^.^.^.^.^...^.
+            ...............
+            "
+        );
+        assert_eq!(
+            sweep(input.lines(), 1u64),
+            Ok(Sweep {
+                splits: 21,
+                timelines: 40
+            })
+        );
+
+        // Splitters on the edges drop the side out of the grid
+        assert_eq!(
+            sweep(["S.", "^.", ".^"], 1u64),
+            Ok(Sweep {
+                splits: 2,
+                timelines: 1
+            })
+        );
+
+        // Modular counts can be 0 while beams are still there
+        let result = sweep(input.lines(), Modular::new(1, 2)).unwrap();
+        assert_eq!(result.splits, 21);
+        assert_eq!(result.timelines, Modular::new(0, 2));
+
+        assert_eq!(
+            sweep(["S.", "/."], 1u64),
+            Err(TimelineError::UnsupportedDevice {
+                device: '/',
+                pos: Coord::new(0, 1)
+            })
+        );
+        assert_eq!(run("S.\n/.\n".to_string()), Ok(1));
+    }
+
+    #[test]
+    fn test_paths() {
+        let grid = Grid::from(indoc!(
+            r"
+            ...S...
+            .......
+            ...^...
+            .......
+            ..^.^..
+            .......
+            "
+        ));
+        let (l, r) = (Choice::Left, Choice::Right);
+        let (top, left, right) = (Coord::new(3, 2), Coord::new(2, 4), Coord::new(4, 4));
+        let expected = vec![
+            vec![(top, l), (left, l)],
+            vec![(top, l), (left, r)],
+            vec![(top, r), (right, l)],
+            vec![(top, r), (right, r)],
+        ];
+
+        let paths: Vec<Timeline> = grid.paths().unwrap().collect();
+        assert_eq!(paths, expected);
+
+        for (k, timeline) in expected.iter().enumerate() {
+            assert_eq!(grid.nth_timeline(k as u128), Ok(Some(timeline.clone())));
+        }
+        assert_eq!(grid.nth_timeline(4), Ok(None));
+
+        let sampler = grid.sampler().unwrap();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let mut seen = HashSet::new();
+        for _ in 0..100 {
+            let timeline = sampler.sample(&mut rng).unwrap();
+            assert!(expected.contains(&timeline));
+            seen.insert(timeline);
+        }
+        assert_eq!(seen.len(), 4);
+    }
+
+    #[test]
+    fn test_paths_example() {
+        let input = read_input(Some(indoc!(
+            r"
+            .......S.......
+            ...............
+            .......^.......
+            ...............
+            ......^.^......
+            ...............
+            .....^.^.^.....
+            ...............
+            ....^.^...^....
+            ...............
+            ...^.^...^.^...
+            ...............
+            ..^...^.....^..
+            ...............
+            .^.^.^.^.^...^.
+            ...............
+            "
+        )));
+        let grid = Grid::from(input.as_str());
+        let paths: Vec<Timeline> = grid.paths().unwrap().collect();
+        assert_eq!(paths.len(), 40);
+        let choices: Vec<Vec<Choice>> = paths
+            .iter()
+            .map(|timeline| timeline.iter().map(|(_, choice)| *choice).collect())
+            .collect();
+        assert!(choices.is_sorted());
+        assert_eq!(grid.nth_timeline(39), Ok(paths.last().cloned()));
+        assert_eq!(grid.nth_timeline(17), Ok(Some(paths[17].clone())));
+    }
+
+    #[test]
+    fn test_format_timeline() {
+        let timeline = vec![
+            (Coord::new(7, 2), Choice::Left),
+            (Coord::new(6, 4), Choice::Right),
+        ];
+        assert_eq!(format_timeline(&timeline), "(7,2)L (6,4)R");
+    }
+
+    #[test]
+    fn test_sources() {
+        let input = indoc!(
+            r"
+            .S...S.
+            .......
+            .^...^.
+            .......
+            ....^..
+            .......
+            "
+        );
+        let mut grid = Grid::from(input);
+        assert_eq!(grid.start, Coord::new(1, 0));
+        assert_eq!(grid.sources.len(), 2);
+        assert_eq!(grid.splits(), 3);
+        assert_eq!(grid.timelines(), Ok(5));
+        assert_eq!(
+            sweep(input.lines(), 1usize).map(|result| result.timelines),
+            Ok(5)
+        );
+
+        let (left, right) = (Coord::new(1, 0), Coord::new(5, 0));
+        grid.set_weight(&right, 10);
+        assert_eq!(grid.timelines(), Ok(2 + 3 * 10));
+        assert_eq!(
+            grid.timelines_with(Modular::new(1, 7)),
+            Ok(Modular::new(4, 7))
+        );
+
+        let histogram = grid.exit_histogram().unwrap();
+        assert_eq!(
+            histogram.keys().copied().collect::<Vec<_>>(),
+            vec![0, 2, 3, 5, 6]
+        );
+        assert_eq!(histogram[&0].timelines, 1);
+        assert_eq!(histogram[&0].by_source, HashMap::from([(left, 1)]));
+        assert_eq!(histogram[&3].timelines, 10);
+        assert_eq!(histogram[&3].by_source, HashMap::from([(right, 10)]));
+        let total: u128 = histogram.values().map(|exit| exit.timelines).sum();
+        assert_eq!(total, 32);
+
+        // Every source is enumerated, each of its timelines once per unit of weight
+        let paths: Vec<Timeline> = grid.paths().unwrap().collect();
+        assert_eq!(paths.len(), 32);
+        assert_eq!(paths[0], vec![(Coord::new(1, 2), Choice::Left)]);
+        assert_eq!(
+            paths[2],
+            vec![
+                (Coord::new(5, 2), Choice::Left),
+                (Coord::new(4, 4), Choice::Left)
+            ]
+        );
+        assert_eq!(paths[2..5], paths[29..32]);
+        for (k, timeline) in paths.iter().enumerate() {
+            assert_eq!(grid.nth_timeline(k as u128), Ok(Some(timeline.clone())));
+        }
+        assert_eq!(grid.nth_timeline(32), Ok(None));
+
+        let sampler = grid.sampler().unwrap();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let from_left = (0..1000)
+            .map(|_| sampler.sample(&mut rng).unwrap())
+            .filter(|timeline| timeline[0].0 == Coord::new(1, 2))
+            .count();
+        assert!((20..150).contains(&from_left), "{}", from_left);
+
+        // Both sources feeding the same exit
+        let grid = Grid::from("..S.S..\n.......\n..^.^..\n.......");
+        let histogram = grid.exit_histogram().unwrap();
+        assert_eq!(histogram[&3].timelines, 2);
+        assert_eq!(
+            histogram[&3].by_source,
+            HashMap::from([(Coord::new(2, 0), 1), (Coord::new(4, 0), 1)])
+        );
     }
 }
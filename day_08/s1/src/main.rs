@@ -1,12 +1,18 @@
-use itertools::Itertools;
 use nom::{
-    character::complete::{char, digit1, line_ending},
-    combinator::{map, map_res, opt},
+    IResult, Parser,
+    character::complete::{char, line_ending},
+    combinator::{all_consuming, map, opt, verify},
     multi::separated_list1,
+    number::complete::double,
     sequence::terminated,
-    IResult, Parser,
 };
-use std::collections::HashMap;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use std::env;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::ops::{Add, Mul};
 
 fn read_input(input: Option<&str>) -> String {
     let input = match input {
@@ -17,19 +23,15 @@ fn read_input(input: Option<&str>) -> String {
     input.to_string()
 }
 
-fn parse_usize(input: &str) -> IResult<&str, usize> {
-    map_res(digit1, str::parse::<usize>).parse(input)
-}
-
-fn parse_junction(input: &str) -> IResult<&str, Junction> {
+fn parse_junction<C: Coordinate>(input: &str) -> IResult<&str, Junction<C>> {
     map(
-        (parse_usize, char(','), parse_usize, char(','), parse_usize),
+        (C::parse, char(','), C::parse, char(','), C::parse),
         |(x, _, y, _, z)| Junction { x, y, z },
     )
     .parse(input)
 }
 
-fn parse(input: &str) -> IResult<&str, Data> {
+fn parse<C: Coordinate>(input: &str) -> IResult<&str, Data<C>> {
     map(
         terminated(
             separated_list1(line_ending, parse_junction),
@@ -40,36 +42,207 @@ fn parse(input: &str) -> IResult<&str, Data> {
     .parse(input)
 }
 
+/// Line that is not a valid junction, `line` is 1-based.
 #[derive(Debug, PartialEq, Eq)]
-struct Data {
-    junctions: Vec<Junction>,
+struct InvalidJunction {
+    line: usize,
+    text: String,
+}
+
+impl fmt::Display for InvalidJunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: invalid junction {}", self.line, self.text)
+    }
+}
+
+impl std::error::Error for InvalidJunction {}
+
+/// Parse the whole input, failing on the first line that is not a junction
+/// (bad syntax or a coordinate out of range).
+fn read_junctions<C: Coordinate>(input: &str) -> Result<Data<C>, InvalidJunction> {
+    match all_consuming(parse::<C>).parse(input) {
+        Ok((_, data)) => Ok(data),
+        Err(nom::Err::Error(error) | nom::Err::Failure(error)) => {
+            let offset = input.len() - error.input.len();
+            let start = input[..offset].rfind('\n').map_or(0, |idx| idx + 1);
+            Err(InvalidJunction {
+                line: input[..start].matches('\n').count() + 1,
+                text: input[start..]
+                    .lines()
+                    .next()
+                    .unwrap_or_default()
+                    .to_string(),
+            })
+        }
+        Err(nom::Err::Incomplete(_)) => unreachable!("complete parsers"),
+    }
+}
+
+fn report(error: InvalidJunction) -> ! {
+    eprintln!("{}", error);
+    std::process::exit(1);
+}
+
+/// Junction coordinate: a signed integer or a float.
+trait Coordinate: Copy + Ord + Hash + fmt::Debug + fmt::Display {
+    /// Wide enough for sums of squared differences without overflow.
+    type Distance: Distance;
+
+    fn parse(input: &str) -> IResult<&str, Self>;
+
+    /// Absolute difference, in the distance type.
+    fn diff(self, other: Self) -> Self::Distance;
+}
+
+/// Distance between junctions under some metric.
+trait Distance: Copy + Ord + fmt::Debug + Add<Output = Self> + Mul<Output = Self> {
+    fn from_weight(weight: u32) -> Self;
+}
+
+/// Largest integer coordinate magnitude: differences stay below 2^63,
+/// so three squared differences still fit in a `u128`.
+const COORDINATE_LIMIT: u64 = 1 << 62;
+
+impl Coordinate for i64 {
+    type Distance = u128;
+
+    fn parse(input: &str) -> IResult<&str, i64> {
+        verify(nom::character::complete::i64, |value: &i64| {
+            value.unsigned_abs() < COORDINATE_LIMIT
+        })
+        .parse(input)
+    }
+
+    fn diff(self, other: i64) -> u128 {
+        (i128::from(self) - i128::from(other)).unsigned_abs()
+    }
+}
+
+impl Distance for u128 {
+    fn from_weight(weight: u32) -> u128 {
+        u128::from(weight)
+    }
+}
+
+/// `f64` under the IEEE 754 total order, so float junctions sort and hash deterministically.
+#[derive(Debug, Clone, Copy)]
+struct Float(f64);
+
+impl PartialEq for Float {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Float {}
+
+impl PartialOrd for Float {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Float {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl Hash for Float {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
-struct Junction {
-    x: usize,
-    y: usize,
-    z: usize,
+impl fmt::Display for Float {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Add for Float {
+    type Output = Float;
+
+    fn add(self, other: Float) -> Float {
+        Float(self.0 + other.0)
+    }
+}
+
+impl Mul for Float {
+    type Output = Float;
+
+    fn mul(self, other: Float) -> Float {
+        Float(self.0 * other.0)
+    }
+}
+
+impl Coordinate for Float {
+    type Distance = Float;
+
+    fn parse(input: &str) -> IResult<&str, Float> {
+        map(double, Float).parse(input)
+    }
+
+    fn diff(self, other: Float) -> Float {
+        Float((self.0 - other.0).abs())
+    }
+}
+
+impl Distance for Float {
+    fn from_weight(weight: u32) -> Float {
+        Float(f64::from(weight))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Data<C> {
+    junctions: Vec<Junction<C>>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord)]
+struct Junction<C> {
+    x: C,
+    y: C,
+    z: C,
 }
 
 #[derive(Debug, PartialEq, Clone)]
-struct JunctionPair {
-    a: Junction,
-    b: Junction,
-    distance: f64,
+struct JunctionPair<C: Coordinate> {
+    a: Junction<C>,
+    b: Junction<C>,
+    distance: C::Distance,
 }
 
 #[derive(Debug)]
 struct DisjointSet {
     parent: Vec<usize>,
     rank: Vec<usize>,
+    /// Component size, valid at roots only
+    size: Vec<usize>,
+    /// Members of each component form a cycle through `next`
+    next: Vec<usize>,
+    components: usize,
+    /// Number of components of each size
+    size_counts: BTreeMap<usize, usize>,
 }
 
-fn euclidean_distance(a: Junction, b: Junction) -> f64 {
-    let dx = a.x.abs_diff(b.x) as f64;
-    let dy = a.y.abs_diff(b.y) as f64;
-    let dz = a.z.abs_diff(b.z) as f64;
-    (dx * dx + dy * dy + dz * dz).sqrt()
+/// Circuit structure at some point of the connection sweep.
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct Snapshot {
+    component_count: usize,
+    size_counts: BTreeMap<usize, usize>,
+}
+
+impl Snapshot {
+    /// Sizes of the `k` largest components, largest first.
+    fn largest(&self, k: usize) -> Vec<usize> {
+        self.size_counts
+            .iter()
+            .rev()
+            .flat_map(|(size, count)| std::iter::repeat_n(*size, *count))
+            .take(k)
+            .collect()
+    }
 }
 
 impl DisjointSet {
@@ -77,6 +250,14 @@ impl DisjointSet {
         Self {
             parent: (0..size).collect(),
             rank: vec![0; size],
+            size: vec![1; size],
+            next: (0..size).collect(),
+            components: size,
+            size_counts: if size > 0 {
+                BTreeMap::from([(1, size)])
+            } else {
+                BTreeMap::new()
+            },
         }
     }
 
@@ -104,68 +285,562 @@ impl DisjointSet {
         if self.rank[root_a] == self.rank[root_b] {
             self.rank[root_a] += 1;
         }
+
+        for size in [self.size[root_a], self.size[root_b]] {
+            let count = self.size_counts.get_mut(&size).expect("size is counted");
+            *count -= 1;
+            if *count == 0 {
+                self.size_counts.remove(&size);
+            }
+        }
+        self.size[root_a] += self.size[root_b];
+        *self.size_counts.entry(self.size[root_a]).or_default() += 1;
+        // Splicing two cycles into one is a swap of successors
+        self.next.swap(root_a, root_b);
+        self.components -= 1;
+    }
+
+    fn size_of(&mut self, x: usize) -> usize {
+        let root = self.find(x);
+        self.size[root]
+    }
+
+    fn component_count(&self) -> usize {
+        self.components
+    }
+
+    /// Every element in the same component as `x`, starting with `x`.
+    fn members(&mut self, x: usize) -> Vec<usize> {
+        let mut members = Vec::with_capacity(self.size_of(x));
+        members.push(x);
+        let mut current = self.next[x];
+        while current != x {
+            members.push(current);
+            current = self.next[current];
+        }
+        members
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            component_count: self.component_count(),
+            size_counts: self.size_counts.clone(),
+        }
     }
 }
 
-fn build_pairs(junctions: &[Junction]) -> Vec<JunctionPair> {
-    let mut pairs: Vec<JunctionPair> = junctions
-        .iter()
-        .copied()
-        .combinations(2)
-        .map(|combo| {
-            let [a, b]: [Junction; 2] = combo.try_into().unwrap();
-            let distance = euclidean_distance(a, b);
-            JunctionPair { a, b, distance }
-        })
-        .collect();
+fn axis<C: Coordinate>(junction: &Junction<C>, axis: usize) -> C {
+    match axis {
+        0 => junction.x,
+        1 => junction.y,
+        _ => junction.z,
+    }
+}
 
-    pairs.sort_by(|left, right| {
-        left.distance
-            .partial_cmp(&right.distance)
-            .expect("distances are finite")
-    });
+/// Distance between junctions, usable by the k-d tree as long as `axis_bound`
+/// never exceeds the distance of two junctions that far apart on that axis.
+trait Metric<C: Coordinate> {
+    fn distance(&self, a: Junction<C>, b: Junction<C>) -> C::Distance;
 
-    pairs
+    /// Smallest possible distance between junctions `diff` apart along `axis`.
+    fn axis_bound(&self, axis: usize, diff: C::Distance) -> C::Distance;
 }
 
-fn run_with_limit(input: String, pairs_to_connect: usize) -> usize {
-    let (_, data) = parse(&input).unwrap();
-    let pairs = build_pairs(&data.junctions);
+/// Squared straight-line distance, compared without taking roots.
+struct EuclideanSquared;
+
+impl<C: Coordinate> Metric<C> for EuclideanSquared {
+    fn distance(&self, a: Junction<C>, b: Junction<C>) -> C::Distance {
+        let dx = a.x.diff(b.x);
+        let dy = a.y.diff(b.y);
+        let dz = a.z.diff(b.z);
+
+        dx * dx + dy * dy + dz * dz
+    }
+
+    fn axis_bound(&self, _axis: usize, diff: C::Distance) -> C::Distance {
+        diff * diff
+    }
+}
+
+/// Cable length along trays, one axis at a time.
+struct Manhattan;
+
+impl<C: Coordinate> Metric<C> for Manhattan {
+    fn distance(&self, a: Junction<C>, b: Junction<C>) -> C::Distance {
+        a.x.diff(b.x) + a.y.diff(b.y) + a.z.diff(b.z)
+    }
+
+    fn axis_bound(&self, _axis: usize, diff: C::Distance) -> C::Distance {
+        diff
+    }
+}
+
+struct Chebyshev;
+
+impl<C: Coordinate> Metric<C> for Chebyshev {
+    fn distance(&self, a: Junction<C>, b: Junction<C>) -> C::Distance {
+        a.x.diff(b.x).max(a.y.diff(b.y)).max(a.z.diff(b.z))
+    }
+
+    fn axis_bound(&self, _axis: usize, diff: C::Distance) -> C::Distance {
+        diff
+    }
+}
+
+/// Manhattan distance with a cost per unit on each axis, e.g. for expensive vertical runs.
+struct Weighted {
+    weights: [u32; 3],
+}
+
+impl<C: Coordinate> Metric<C> for Weighted {
+    fn distance(&self, a: Junction<C>, b: Junction<C>) -> C::Distance {
+        let weighted = |idx: usize| {
+            C::Distance::from_weight(self.weights[idx]) * axis(&a, idx).diff(axis(&b, idx))
+        };
+
+        weighted(0) + weighted(1) + weighted(2)
+    }
+
+    fn axis_bound(&self, axis: usize, diff: C::Distance) -> C::Distance {
+        C::Distance::from_weight(self.weights[axis]) * diff
+    }
+}
+
+/// `euclidean`, `manhattan`, `chebyshev` or `weighted:x,y,z`.
+fn parse_metric<C: Coordinate>(name: &str) -> Option<Box<dyn Metric<C>>> {
+    match name {
+        "euclidean" => Some(Box::new(EuclideanSquared)),
+        "manhattan" => Some(Box::new(Manhattan)),
+        "chebyshev" => Some(Box::new(Chebyshev)),
+        _ => {
+            let weights: Vec<u32> = name
+                .strip_prefix("weighted:")?
+                .split(',')
+                .map(|weight| weight.parse().ok())
+                .collect::<Option<_>>()?;
+            Some(Box::new(Weighted {
+                weights: weights.try_into().ok()?,
+            }))
+        }
+    }
+}
+
+/// k-d tree over the junctions, stored as an implicit balanced tree:
+/// the node of a slice is its middle element, children are the two halves.
+struct KdTree<'a, C: Coordinate> {
+    junctions: &'a [Junction<C>],
+    metric: &'a dyn Metric<C>,
+    nodes: Vec<usize>,
+}
 
-    let mut index_by_junction: HashMap<Junction, usize> = HashMap::new();
-    for (idx, junction) in data.junctions.iter().copied().enumerate() {
-        index_by_junction.insert(junction, idx);
+impl<'a, C: Coordinate> KdTree<'a, C> {
+    fn new(junctions: &'a [Junction<C>], metric: &'a dyn Metric<C>) -> Self {
+        fn build<C: Coordinate>(junctions: &[Junction<C>], nodes: &mut [usize], depth: usize) {
+            if nodes.len() <= 1 {
+                return;
+            }
+            let mid = nodes.len() / 2;
+            nodes.select_nth_unstable_by_key(mid, |idx| axis(&junctions[*idx], depth % 3));
+            let (left, right) = nodes.split_at_mut(mid);
+            build(junctions, left, depth + 1);
+            build(junctions, &mut right[1..], depth + 1);
+        }
+
+        let mut nodes: Vec<usize> = (0..junctions.len()).collect();
+        build(junctions, &mut nodes, 0);
+        KdTree {
+            junctions,
+            metric,
+            nodes,
+        }
+    }
+
+    /// The `k` other junctions closest to `target`, ordered by distance then junction.
+    fn nearest(&self, target: usize, k: usize) -> Vec<Neighbour<C>> {
+        let mut best: BinaryHeap<Neighbour<C>> = BinaryHeap::new();
+        self.search(&self.nodes, 0, target, k, &mut best);
+        best.into_sorted_vec()
+    }
+
+    fn search(
+        &self,
+        nodes: &[usize],
+        depth: usize,
+        target: usize,
+        k: usize,
+        best: &mut BinaryHeap<Neighbour<C>>,
+    ) {
+        if nodes.is_empty() || k == 0 {
+            return;
+        }
+        let mid = nodes.len() / 2;
+        let idx = nodes[mid];
+        let (point, goal) = (self.junctions[idx], self.junctions[target]);
+
+        if idx != target {
+            let candidate = Neighbour {
+                distance: self.metric.distance(point, goal),
+                junction: point,
+                idx,
+            };
+            if best.len() < k {
+                best.push(candidate);
+            } else if candidate < *best.peek().unwrap() {
+                best.pop();
+                best.push(candidate);
+            }
+        }
+
+        let (left, right) = (&nodes[..mid], &nodes[mid + 1..]);
+        let bound = self.metric.axis_bound(
+            depth % 3,
+            axis(&goal, depth % 3).diff(axis(&point, depth % 3)),
+        );
+        let (near, far) = if axis(&goal, depth % 3) < axis(&point, depth % 3) {
+            (left, right)
+        } else {
+            (right, left)
+        };
+        self.search(near, depth + 1, target, k, best);
+        // Equal distances must be visited too, ties are broken on the junction
+        if best.len() < k || bound <= best.peek().unwrap().distance {
+            self.search(far, depth + 1, target, k, best);
+        }
+    }
+}
+
+/// Junction found by a nearest neighbour query, ordered by distance then junction.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+struct Neighbour<C: Coordinate> {
+    distance: C::Distance,
+    junction: Junction<C>,
+    idx: usize,
+}
+
+/// Neighbours of one junction, fetched from the tree by doubling batches.
+#[derive(Debug)]
+struct NeighbourStream<C: Coordinate> {
+    batch: Vec<Neighbour<C>>,
+    consumed: usize,
+    k: usize,
+}
+
+impl<C: Coordinate> NeighbourStream<C> {
+    fn next(&mut self, tree: &KdTree<C>, idx: usize) -> Option<Neighbour<C>> {
+        if self.consumed == self.batch.len() {
+            // A short batch means every other junction has been seen
+            if self.batch.len() < self.k {
+                return None;
+            }
+            self.k *= 2;
+            self.batch = tree.nearest(idx, self.k);
+        }
+        let neighbour = self.batch.get(self.consumed).copied();
+        self.consumed += 1;
+        neighbour
+    }
+}
+
+/// Sort key of a pair: distance, first junction, second junction.
+type PairKey<C> = (<C as Coordinate>::Distance, Junction<C>, Junction<C>);
+
+/// Junction pairs in increasing distance order (ties broken on the junctions),
+/// generated lazily by merging the neighbour stream of every junction.
+struct ClosestPairs<'a, C: Coordinate> {
+    junctions: &'a [Junction<C>],
+    tree: KdTree<'a, C>,
+    streams: Vec<NeighbourStream<C>>,
+    /// Next candidate of each stream, with the stream it came from.
+    heap: BinaryHeap<Reverse<(PairKey<C>, usize)>>,
+    last: Option<PairKey<C>>,
+}
+
+impl<'a, C: Coordinate> ClosestPairs<'a, C> {
+    fn new(junctions: &'a [Junction<C>], metric: &'a dyn Metric<C>) -> Self {
+        let mut pairs = ClosestPairs {
+            junctions,
+            tree: KdTree::new(junctions, metric),
+            streams: Vec::with_capacity(junctions.len()),
+            heap: BinaryHeap::new(),
+            last: None,
+        };
+        for idx in 0..junctions.len() {
+            let k = 4;
+            pairs.streams.push(NeighbourStream {
+                batch: pairs.tree.nearest(idx, k),
+                consumed: 0,
+                k,
+            });
+            pairs.advance(idx);
+        }
+        pairs
+    }
+
+    fn advance(&mut self, idx: usize) {
+        if let Some(neighbour) = self.streams[idx].next(&self.tree, idx) {
+            let a = self.junctions[idx].min(neighbour.junction);
+            let b = self.junctions[idx].max(neighbour.junction);
+            self.heap.push(Reverse(((neighbour.distance, a, b), idx)));
+        }
+    }
+}
+
+impl<C: Coordinate> Iterator for ClosestPairs<'_, C> {
+    type Item = JunctionPair<C>;
+
+    fn next(&mut self) -> Option<JunctionPair<C>> {
+        // Every pair comes from the streams of both its junctions, skip the copy
+        while let Some(Reverse(((distance, a, b), idx))) = self.heap.pop() {
+            self.advance(idx);
+            if self.last == Some((distance, a, b)) {
+                continue;
+            }
+            self.last = Some((distance, a, b));
+            return Some(JunctionPair { a, b, distance });
+        }
+        None
+    }
+}
+
+/// Circuits formed by connecting the closest pairs one at a time.
+struct Circuits<'a, C: Coordinate> {
+    junctions: &'a [Junction<C>],
+    index_by_junction: HashMap<Junction<C>, usize>,
+    pairs: ClosestPairs<'a, C>,
+    dsu: DisjointSet,
+    connections: usize,
+}
+
+impl<'a, C: Coordinate> Circuits<'a, C> {
+    fn new(junctions: &'a [Junction<C>], metric: &'a dyn Metric<C>) -> Self {
+        let mut index_by_junction: HashMap<Junction<C>, usize> = HashMap::new();
+        for (idx, junction) in junctions.iter().copied().enumerate() {
+            index_by_junction.insert(junction, idx);
+        }
+
+        Circuits {
+            junctions,
+            index_by_junction,
+            pairs: ClosestPairs::new(junctions, metric),
+            dsu: DisjointSet::new(junctions.len()),
+            connections: 0,
+        }
     }
 
-    let mut dsu = DisjointSet::new(data.junctions.len());
-    for pair in pairs.iter().take(pairs_to_connect) {
-        let a_idx = *index_by_junction.get(&pair.a).expect("junction exists");
-        let b_idx = *index_by_junction.get(&pair.b).expect("junction exists");
-        dsu.union(a_idx, b_idx);
+    /// Make connections until `connections` have been made or no pairs are left.
+    fn connect_until(&mut self, connections: usize) {
+        while self.connections < connections {
+            let Some(pair) = self.pairs.next() else {
+                return;
+            };
+            let a_idx = self.index_by_junction[&pair.a];
+            let b_idx = self.index_by_junction[&pair.b];
+            self.dsu.union(a_idx, b_idx);
+            self.connections += 1;
+        }
     }
 
-    let mut counts: HashMap<usize, usize> = HashMap::new();
-    for idx in 0..data.junctions.len() {
-        let root = dsu.find(idx);
-        *counts.entry(root).or_default() += 1;
+    /// Junctions in the same circuit as `junction`.
+    fn circuit_of(&mut self, junction: Junction<C>) -> Option<Vec<Junction<C>>> {
+        let idx = *self.index_by_junction.get(&junction)?;
+        let mut members = self.dsu.members(idx);
+        members.sort_unstable();
+        Some(members.iter().map(|idx| self.junctions[*idx]).collect())
     }
+}
+
+/// Online version of the connection sweep: junctions arrive one at a time and
+/// the `limit` closest pairs seen so far are kept up to date.
+struct OnlineCircuits<C: Coordinate> {
+    metric: Box<dyn Metric<C>>,
+    limit: usize,
+    junctions: Vec<Junction<C>>,
+    index_by_junction: HashMap<Junction<C>, usize>,
+    /// The closest pairs, with the indices of their junctions
+    closest: BTreeMap<PairKey<C>, (usize, usize)>,
+}
 
-    let mut sizes: Vec<usize> = counts.values().copied().collect();
-    sizes.sort_unstable_by(|a, b| b.cmp(a));
+impl<C: Coordinate> OnlineCircuits<C> {
+    fn new(metric: Box<dyn Metric<C>>, limit: usize) -> Self {
+        OnlineCircuits {
+            metric,
+            limit,
+            junctions: Vec::new(),
+            index_by_junction: HashMap::new(),
+            closest: BTreeMap::new(),
+        }
+    }
 
-    sizes.iter().take(3).product()
+    /// Add a junction, returning false if it is already known. Every existing
+    /// junction is a candidate partner, so this is linear in the junction count.
+    fn insert(&mut self, junction: Junction<C>) -> bool {
+        if self.index_by_junction.contains_key(&junction) {
+            return false;
+        }
+        let idx = self.junctions.len();
+        for (other_idx, other) in self.junctions.iter().copied().enumerate() {
+            let key = (
+                self.metric.distance(junction, other),
+                junction.min(other),
+                junction.max(other),
+            );
+            if self.closest.len() < self.limit {
+                self.closest.insert(key, (idx, other_idx));
+            } else if self
+                .closest
+                .last_key_value()
+                .is_some_and(|(furthest, _)| key < *furthest)
+            {
+                self.closest.pop_last();
+                self.closest.insert(key, (idx, other_idx));
+            }
+        }
+        self.junctions.push(junction);
+        self.index_by_junction.insert(junction, idx);
+        true
+    }
+
+    /// Circuits formed by the current closest pairs.
+    fn snapshot(&self) -> Snapshot {
+        let mut dsu = DisjointSet::new(self.junctions.len());
+        for (a_idx, b_idx) in self.closest.values() {
+            dsu.union(*a_idx, *b_idx);
+        }
+        dsu.snapshot()
+    }
+}
+
+/// Snapshots of the circuits after each number of connections in `checkpoints`,
+/// taken in a single pass over the closest pairs.
+fn circuits_after<C: Coordinate>(
+    junctions: &[Junction<C>],
+    metric: &dyn Metric<C>,
+    checkpoints: &[usize],
+) -> Vec<Snapshot> {
+    let mut order: Vec<usize> = (0..checkpoints.len()).collect();
+    order.sort_by_key(|idx| checkpoints[*idx]);
+
+    let mut circuits = Circuits::new(junctions, metric);
+    let mut snapshots = vec![None; checkpoints.len()];
+    for idx in order {
+        circuits.connect_until(checkpoints[idx]);
+        snapshots[idx] = Some(circuits.dsu.snapshot());
+    }
+
+    snapshots.into_iter().flatten().collect()
+}
+
+fn run_with_limit(input: String, pairs_to_connect: usize) -> usize {
+    let data = read_junctions::<i64>(&input).unwrap_or_else(|error| report(error));
+    let snapshots = circuits_after(&data.junctions, &EuclideanSquared, &[pairs_to_connect]);
+
+    snapshots[0].largest(3).iter().product()
 }
 
 fn run(input: String) -> usize {
     run_with_limit(input, 1000)
 }
 
+/// Handles `--online`, `--after`, `--circuit` and `--metric`; returns the answer
+/// unless a report was printed.
+fn explore<C: Coordinate>(input: &str, args: &[String]) -> Option<usize> {
+    // `--metric euclidean|manhattan|chebyshev|weighted:x,y,z`
+    let metric: Box<dyn Metric<C>> = match args.iter().position(|arg| arg == "--metric") {
+        Some(pos) => args
+            .get(pos + 1)
+            .and_then(|name| parse_metric(name))
+            .expect("Invalid metric"),
+        None => Box::new(EuclideanSquared),
+    };
+    if args.iter().any(|arg| arg == "--online") {
+        // Junctions from stdin, one per line; `?` prints the current circuits
+        let mut online = OnlineCircuits::new(metric, 1000);
+        for line in io::stdin().lines() {
+            let line = line.expect("Failed to read stdin");
+            match line.trim() {
+                "" => {}
+                "?" => {
+                    let snapshot = online.snapshot();
+                    let largest = snapshot.largest(3);
+                    println!(
+                        "{} junctions, {} circuits, largest {:?}, product {}",
+                        online.junctions.len(),
+                        snapshot.component_count,
+                        largest,
+                        largest.iter().product::<usize>()
+                    );
+                }
+                junction => match parse_junction::<C>(junction) {
+                    Ok(("", junction)) => {
+                        if !online.insert(junction) {
+                            eprintln!("warning: duplicate junction {}", line.trim());
+                        }
+                    }
+                    _ => eprintln!("warning: ignoring invalid junction {}", line.trim()),
+                },
+            }
+        }
+        return Some(online.snapshot().largest(3).iter().product());
+    }
+    let data = read_junctions::<C>(input).unwrap_or_else(|error| report(error));
+    if let Some(pos) = args.iter().position(|arg| arg == "--after") {
+        // `--after N,N,...`
+        let checkpoints: Vec<usize> = args
+            .get(pos + 1)
+            .expect("Missing connection counts")
+            .split(',')
+            .map(|count| count.parse().expect("Invalid connection count"))
+            .collect();
+        let snapshots = circuits_after(&data.junctions, metric.as_ref(), &checkpoints);
+        for (count, snapshot) in checkpoints.iter().zip(snapshots.iter()) {
+            let largest = snapshot.largest(3);
+            println!(
+                "After {} connections: {} circuits, largest {:?}, product {}",
+                count,
+                snapshot.component_count,
+                largest,
+                largest.iter().product::<usize>()
+            );
+        }
+        return None;
+    }
+    if let Some(pos) = args.iter().position(|arg| arg == "--circuit") {
+        // `--circuit x,y,z`: the circuit of a junction after the usual 1000 connections
+        let (_, junction) = args
+            .get(pos + 1)
+            .and_then(|junction| parse_junction::<C>(junction).ok())
+            .expect("Invalid junction");
+        let mut circuits = Circuits::new(&data.junctions, metric.as_ref());
+        circuits.connect_until(1000);
+        let members = circuits.circuit_of(junction).expect("Unknown junction");
+        println!("Circuit of {} junctions:", members.len());
+        for Junction { x, y, z } in members {
+            println!("{},{},{}", x, y, z);
+        }
+        return None;
+    }
+
+    let snapshots = circuits_after(&data.junctions, metric.as_ref(), &[1000]);
+    Some(snapshots[0].largest(3).iter().product())
+}
+
 fn main() {
     let input = read_input(None);
 
-    let answer = run(input);
+    let args: Vec<String> = env::args().collect();
+    let answer = if args.iter().any(|arg| arg == "--float") {
+        // Fractional coordinates
+        explore::<Float>(&input, &args)
+    } else if args.len() > 1 {
+        explore::<i64>(&input, &args)
+    } else {
+        Some(run(input))
+    };
 
-    println!("Answer: {}", answer);
+    if let Some(answer) = answer {
+        println!("Answer: {}", answer);
+    }
 }
 
 #[allow(unused_imports)]
@@ -174,17 +849,11 @@ mod tests {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
     use super::*;
     use indoc::indoc;
+    use itertools::Itertools;
     use pretty_assertions::{assert_eq, assert_ne};
 
-    #[test]
-    fn test_fake() {
-        assert_eq!(1, 1);
-    }
-
-    #[test]
-    fn test_run1() {
-        let input = read_input(Some(indoc!(
-            r"
+    const EXAMPLE: &str = indoc!(
+        r"
             162,817,812
             57,618,57
             906,360,560
@@ -205,9 +874,186 @@ mod tests {
             862,61,35
             984,92,344
             425,690,689
-            "
-        )));
+        "
+    );
+
+    #[test]
+    fn test_fake() {
+        assert_eq!(1, 1);
+    }
+
+    #[test]
+    fn test_run1() {
+        let input = read_input(Some(EXAMPLE));
         let answer = run_with_limit(input, 10);
         assert_eq!(answer, 40);
     }
+
+    #[test]
+    fn test_disjoint_set_sizes() {
+        let mut dsu = DisjointSet::new(6);
+        dsu.union(0, 1);
+        dsu.union(2, 3);
+        dsu.union(1, 3);
+        dsu.union(0, 2);
+
+        assert_eq!(dsu.size_of(3), 4);
+        assert_eq!(dsu.size_of(5), 1);
+        assert_eq!(dsu.component_count(), 3);
+        let mut members = dsu.members(2);
+        members.sort();
+        assert_eq!(members, vec![0, 1, 2, 3]);
+        assert_eq!(dsu.members(4), vec![4]);
+        assert_eq!(dsu.snapshot().largest(3), vec![4, 1, 1]);
+    }
+
+    #[test]
+    fn test_circuits_after() {
+        let (_, data) = parse(EXAMPLE).unwrap();
+        let snapshots = circuits_after(&data.junctions, &EuclideanSquared, &[10, 0, 1000]);
+
+        assert_eq!(snapshots[0].component_count, 11);
+        assert_eq!(snapshots[0].largest(4), vec![5, 4, 2, 2]);
+        assert_eq!(snapshots[1].component_count, 20);
+        assert_eq!(snapshots[2].largest(3), vec![20]);
+
+        let mut circuits = Circuits::new(&data.junctions, &EuclideanSquared);
+        circuits.connect_until(1);
+        assert_eq!(
+            circuits.circuit_of(Junction {
+                x: 425,
+                y: 690,
+                z: 689
+            }),
+            Some(vec![
+                Junction {
+                    x: 162,
+                    y: 817,
+                    z: 812
+                },
+                Junction {
+                    x: 425,
+                    y: 690,
+                    z: 689
+                },
+            ])
+        );
+    }
+
+    fn brute_force_pairs<C: Coordinate>(
+        junctions: &[Junction<C>],
+        metric: &dyn Metric<C>,
+    ) -> Vec<JunctionPair<C>> {
+        let mut pairs: Vec<JunctionPair<C>> = junctions
+            .iter()
+            .copied()
+            .tuple_combinations()
+            .map(|(a, b)| JunctionPair {
+                a: a.min(b),
+                b: a.max(b),
+                distance: metric.distance(a, b),
+            })
+            .collect();
+        pairs.sort_by_key(|pair| (pair.distance, pair.a, pair.b));
+        pairs
+    }
+
+    #[test]
+    fn test_closest_pairs_match_brute_force() {
+        // Small coordinates so that many pairs share a distance
+        let mut seed = 12345_usize;
+        let mut next = || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as i64 % 12 - 6
+        };
+        let junctions: Vec<Junction<i64>> = (0..150)
+            .map(|_| Junction {
+                x: next(),
+                y: next(),
+                z: next(),
+            })
+            .unique()
+            .collect();
+        let floats: Vec<Junction<Float>> = junctions
+            .iter()
+            .map(|junction| Junction {
+                x: Float(junction.x as f64 / 4.0),
+                y: Float(junction.y as f64 * 0.1),
+                z: Float(junction.z as f64),
+            })
+            .collect();
+
+        for name in ["euclidean", "manhattan", "chebyshev", "weighted:1,3,2"] {
+            let metric = parse_metric(name).unwrap();
+            let lazy: Vec<_> = ClosestPairs::new(&junctions, metric.as_ref()).collect();
+            assert_eq!(
+                lazy,
+                brute_force_pairs(&junctions, metric.as_ref()),
+                "{}",
+                name
+            );
+
+            let metric = parse_metric(name).unwrap();
+            let lazy: Vec<_> = ClosestPairs::new(&floats, metric.as_ref()).collect();
+            assert_eq!(
+                lazy,
+                brute_force_pairs(&floats, metric.as_ref()),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_online_circuits() {
+        let (_, data) = parse::<i64>(EXAMPLE).unwrap();
+        let mut online = OnlineCircuits::new(Box::new(EuclideanSquared), 10);
+        for (count, junction) in data.junctions.iter().enumerate() {
+            assert!(online.insert(*junction));
+            // Same circuits as a batch sweep over the junctions seen so far
+            let batch = circuits_after(&data.junctions[..=count], &EuclideanSquared, &[10]);
+            assert_eq!(online.snapshot(), batch[0]);
+        }
+        assert!(!online.insert(data.junctions[3]));
+        assert_eq!(online.snapshot().largest(3), vec![5, 4, 2]);
+    }
+
+    #[test]
+    fn test_signed_and_float_coordinates() {
+        let (_, data) = parse::<i64>("-3,0,2\n-5,1,2\n4,4,4").unwrap();
+        assert_eq!(data.junctions[0], Junction { x: -3, y: 0, z: 2 });
+        assert!(<i64 as Coordinate>::parse("-4611686018427387904").is_err());
+        let snapshots = circuits_after(&data.junctions, &EuclideanSquared, &[1]);
+        assert_eq!(snapshots[0].largest(3), vec![2, 1]);
+
+        // An out of range line is reported, not dropped with everything after it
+        assert_eq!(
+            read_junctions::<i64>("1,2,3\n4611686018427387904,0,0\n5,6,7")
+                .map(|data| data.junctions),
+            Err(InvalidJunction {
+                line: 2,
+                text: "4611686018427387904,0,0".to_string()
+            })
+        );
+        assert_eq!(
+            read_junctions::<i64>("1,2,3\n4,5,x\n")
+                .unwrap_err()
+                .to_string(),
+            "line 2: invalid junction 4,5,x"
+        );
+        assert_eq!(
+            read_junctions::<i64>("1,2,3\n4,5,6\n").map(|data| data.junctions.len()),
+            Ok(2)
+        );
+
+        let (_, data) = parse::<Float>("0.5,0,0\n-0.25,0,0\n0.1,0,0").unwrap();
+        let mut circuits = Circuits::new(&data.junctions, &Manhattan);
+        circuits.connect_until(1);
+        assert_eq!(
+            circuits.circuit_of(data.junctions[2]),
+            Some(vec![data.junctions[1], data.junctions[2]])
+        );
+    }
 }
@@ -1,12 +1,18 @@
-use itertools::Itertools;
 use nom::{
     IResult, Parser,
-    character::complete::{char, digit1, line_ending},
-    combinator::{map, map_res, opt},
+    character::complete::{char, line_ending},
+    combinator::{all_consuming, map, opt, verify},
     multi::separated_list1,
+    number::complete::double,
     sequence::terminated,
 };
-use std::collections::HashMap;
+use serde::Serialize;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+use std::env;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::{Add, Mul};
 
 fn read_input(input: Option<&str>) -> String {
     let input = match input {
@@ -17,19 +23,15 @@ fn read_input(input: Option<&str>) -> String {
     input.to_string()
 }
 
-fn parse_usize(input: &str) -> IResult<&str, usize> {
-    map_res(digit1, str::parse::<usize>).parse(input)
-}
-
-fn parse_junction(input: &str) -> IResult<&str, Junction> {
+fn parse_junction<C: Coordinate>(input: &str) -> IResult<&str, Junction<C>> {
     map(
-        (parse_usize, char(','), parse_usize, char(','), parse_usize),
+        (C::parse, char(','), C::parse, char(','), C::parse),
         |(x, _, y, _, z)| Junction { x, y, z },
     )
     .parse(input)
 }
 
-fn parse(input: &str) -> IResult<&str, Data> {
+fn parse<C: Coordinate>(input: &str) -> IResult<&str, Data<C>> {
     map(
         terminated(
             separated_list1(line_ending, parse_junction),
@@ -40,23 +42,186 @@ fn parse(input: &str) -> IResult<&str, Data> {
     .parse(input)
 }
 
+/// Line that is not a valid junction, `line` is 1-based.
 #[derive(Debug, PartialEq, Eq)]
-struct Data {
-    junctions: Vec<Junction>,
+struct InvalidJunction {
+    line: usize,
+    text: String,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord)]
-struct Junction {
-    x: usize,
-    y: usize,
-    z: usize,
+impl fmt::Display for InvalidJunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: invalid junction {}", self.line, self.text)
+    }
+}
+
+impl std::error::Error for InvalidJunction {}
+
+/// Parse the whole input, failing on the first line that is not a junction
+/// (bad syntax or a coordinate out of range).
+fn read_junctions<C: Coordinate>(input: &str) -> Result<Data<C>, InvalidJunction> {
+    match all_consuming(parse::<C>).parse(input) {
+        Ok((_, data)) => Ok(data),
+        Err(nom::Err::Error(error) | nom::Err::Failure(error)) => {
+            let offset = input.len() - error.input.len();
+            let start = input[..offset].rfind('\n').map_or(0, |idx| idx + 1);
+            Err(InvalidJunction {
+                line: input[..start].matches('\n').count() + 1,
+                text: input[start..]
+                    .lines()
+                    .next()
+                    .unwrap_or_default()
+                    .to_string(),
+            })
+        }
+        Err(nom::Err::Incomplete(_)) => unreachable!("complete parsers"),
+    }
 }
 
-#[derive(Debug, PartialEq, Clone)]
-struct JunctionPair {
-    a: Junction,
-    b: Junction,
-    distance_sq: usize,
+fn report(error: InvalidJunction) -> ! {
+    eprintln!("{}", error);
+    std::process::exit(1);
+}
+
+/// Junction coordinate: a signed integer or a float.
+trait Coordinate: Copy + Ord + Hash + fmt::Debug + fmt::Display + Serialize {
+    /// Wide enough for sums of squared differences without overflow.
+    type Distance: Distance;
+
+    fn parse(input: &str) -> IResult<&str, Self>;
+
+    /// Absolute difference, in the distance type.
+    fn diff(self, other: Self) -> Self::Distance;
+}
+
+/// Distance between junctions under some metric.
+trait Distance: Copy + Ord + fmt::Debug + Serialize + Add<Output = Self> + Mul<Output = Self> {
+    fn from_weight(weight: u32) -> Self;
+
+    fn to_f64(self) -> f64;
+}
+
+/// Largest integer coordinate magnitude: differences stay below 2^63,
+/// so three squared differences still fit in a `u128`.
+const COORDINATE_LIMIT: u64 = 1 << 62;
+
+impl Coordinate for i64 {
+    type Distance = u128;
+
+    fn parse(input: &str) -> IResult<&str, i64> {
+        verify(nom::character::complete::i64, |value: &i64| {
+            value.unsigned_abs() < COORDINATE_LIMIT
+        })
+        .parse(input)
+    }
+
+    fn diff(self, other: i64) -> u128 {
+        (i128::from(self) - i128::from(other)).unsigned_abs()
+    }
+}
+
+impl Distance for u128 {
+    fn from_weight(weight: u32) -> u128 {
+        u128::from(weight)
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+/// `f64` under the IEEE 754 total order, so float junctions sort and hash deterministically.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(transparent)]
+struct Float(f64);
+
+impl PartialEq for Float {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Float {}
+
+impl PartialOrd for Float {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Float {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl Hash for Float {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
+}
+
+impl fmt::Display for Float {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Add for Float {
+    type Output = Float;
+
+    fn add(self, other: Float) -> Float {
+        Float(self.0 + other.0)
+    }
+}
+
+impl Mul for Float {
+    type Output = Float;
+
+    fn mul(self, other: Float) -> Float {
+        Float(self.0 * other.0)
+    }
+}
+
+impl Coordinate for Float {
+    type Distance = Float;
+
+    fn parse(input: &str) -> IResult<&str, Float> {
+        map(double, Float).parse(input)
+    }
+
+    fn diff(self, other: Float) -> Float {
+        Float((self.0 - other.0).abs())
+    }
+}
+
+impl Distance for Float {
+    fn from_weight(weight: u32) -> Float {
+        Float(f64::from(weight))
+    }
+
+    fn to_f64(self) -> f64 {
+        self.0
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Data<C> {
+    junctions: Vec<Junction<C>>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord, Serialize)]
+struct Junction<C> {
+    x: C,
+    y: C,
+    z: C,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+struct JunctionPair<C: Coordinate> {
+    a: Junction<C>,
+    b: Junction<C>,
+    distance: C::Distance,
 }
 
 #[derive(Debug)]
@@ -102,71 +267,474 @@ impl DisjointSet {
     }
 }
 
-fn euclidian_distance(a: Junction, b: Junction) -> usize {
-    let dx = a.x.abs_diff(b.x);
-    let dy = a.y.abs_diff(b.y);
-    let dz = a.z.abs_diff(b.z);
+fn axis<C: Coordinate>(junction: &Junction<C>, axis: usize) -> C {
+    match axis {
+        0 => junction.x,
+        1 => junction.y,
+        _ => junction.z,
+    }
+}
+
+/// Distance between junctions, usable by the k-d tree as long as `axis_bound`
+/// never exceeds the distance of two junctions that far apart on that axis.
+trait Metric<C: Coordinate> {
+    fn distance(&self, a: Junction<C>, b: Junction<C>) -> C::Distance;
 
-    dx * dx + dy * dy + dz * dz
+    /// Smallest possible distance between junctions `diff` apart along `axis`.
+    fn axis_bound(&self, axis: usize, diff: C::Distance) -> C::Distance;
+
+    /// Distance as a length, for dendrogram heights.
+    fn length(&self, distance: C::Distance) -> f64 {
+        distance.to_f64()
+    }
+}
+
+/// Squared straight-line distance, compared without taking roots.
+struct EuclideanSquared;
+
+impl<C: Coordinate> Metric<C> for EuclideanSquared {
+    fn distance(&self, a: Junction<C>, b: Junction<C>) -> C::Distance {
+        let dx = a.x.diff(b.x);
+        let dy = a.y.diff(b.y);
+        let dz = a.z.diff(b.z);
+
+        dx * dx + dy * dy + dz * dz
+    }
+
+    fn axis_bound(&self, _axis: usize, diff: C::Distance) -> C::Distance {
+        diff * diff
+    }
+
+    fn length(&self, distance: C::Distance) -> f64 {
+        distance.to_f64().sqrt()
+    }
+}
+
+/// Cable length along trays, one axis at a time.
+struct Manhattan;
+
+impl<C: Coordinate> Metric<C> for Manhattan {
+    fn distance(&self, a: Junction<C>, b: Junction<C>) -> C::Distance {
+        a.x.diff(b.x) + a.y.diff(b.y) + a.z.diff(b.z)
+    }
+
+    fn axis_bound(&self, _axis: usize, diff: C::Distance) -> C::Distance {
+        diff
+    }
+}
+
+struct Chebyshev;
+
+impl<C: Coordinate> Metric<C> for Chebyshev {
+    fn distance(&self, a: Junction<C>, b: Junction<C>) -> C::Distance {
+        a.x.diff(b.x).max(a.y.diff(b.y)).max(a.z.diff(b.z))
+    }
+
+    fn axis_bound(&self, _axis: usize, diff: C::Distance) -> C::Distance {
+        diff
+    }
 }
 
-fn build_pairs(junctions: &[Junction]) -> Vec<JunctionPair> {
-    let mut pairs: Vec<JunctionPair> = junctions
-        .iter()
-        .copied()
-        .combinations(2)
-        .map(|combo| {
-            let [mut a, mut b]: [Junction; 2] = combo.try_into().unwrap();
-            if b < a {
-                std::mem::swap(&mut a, &mut b);
+/// Manhattan distance with a cost per unit on each axis, e.g. for expensive vertical runs.
+struct Weighted {
+    weights: [u32; 3],
+}
+
+impl<C: Coordinate> Metric<C> for Weighted {
+    fn distance(&self, a: Junction<C>, b: Junction<C>) -> C::Distance {
+        let weighted = |idx: usize| {
+            C::Distance::from_weight(self.weights[idx]) * axis(&a, idx).diff(axis(&b, idx))
+        };
+
+        weighted(0) + weighted(1) + weighted(2)
+    }
+
+    fn axis_bound(&self, axis: usize, diff: C::Distance) -> C::Distance {
+        C::Distance::from_weight(self.weights[axis]) * diff
+    }
+}
+
+/// `euclidean`, `manhattan`, `chebyshev` or `weighted:x,y,z`.
+fn parse_metric<C: Coordinate>(name: &str) -> Option<Box<dyn Metric<C>>> {
+    match name {
+        "euclidean" => Some(Box::new(EuclideanSquared)),
+        "manhattan" => Some(Box::new(Manhattan)),
+        "chebyshev" => Some(Box::new(Chebyshev)),
+        _ => {
+            let weights: Vec<u32> = name
+                .strip_prefix("weighted:")?
+                .split(',')
+                .map(|weight| weight.parse().ok())
+                .collect::<Option<_>>()?;
+            Some(Box::new(Weighted {
+                weights: weights.try_into().ok()?,
+            }))
+        }
+    }
+}
+
+/// k-d tree over the junctions, stored as an implicit balanced tree:
+/// the node of a slice is its middle element, children are the two halves.
+struct KdTree<'a, C: Coordinate> {
+    junctions: &'a [Junction<C>],
+    metric: &'a dyn Metric<C>,
+    nodes: Vec<usize>,
+}
+
+impl<'a, C: Coordinate> KdTree<'a, C> {
+    fn new(junctions: &'a [Junction<C>], metric: &'a dyn Metric<C>) -> Self {
+        fn build<C: Coordinate>(junctions: &[Junction<C>], nodes: &mut [usize], depth: usize) {
+            if nodes.len() <= 1 {
+                return;
             }
+            let mid = nodes.len() / 2;
+            nodes.select_nth_unstable_by_key(mid, |idx| axis(&junctions[*idx], depth % 3));
+            let (left, right) = nodes.split_at_mut(mid);
+            build(junctions, left, depth + 1);
+            build(junctions, &mut right[1..], depth + 1);
+        }
 
-            let distance_sq = euclidian_distance(a, b);
+        let mut nodes: Vec<usize> = (0..junctions.len()).collect();
+        build(junctions, &mut nodes, 0);
+        KdTree {
+            junctions,
+            metric,
+            nodes,
+        }
+    }
 
-            JunctionPair { a, b, distance_sq }
-        })
-        .collect();
+    /// The `k` other junctions closest to `target`, ordered by distance then junction.
+    fn nearest(&self, target: usize, k: usize) -> Vec<Neighbour<C>> {
+        let mut best: BinaryHeap<Neighbour<C>> = BinaryHeap::new();
+        self.search(&self.nodes, 0, target, k, &mut best);
+        best.into_sorted_vec()
+    }
+
+    fn search(
+        &self,
+        nodes: &[usize],
+        depth: usize,
+        target: usize,
+        k: usize,
+        best: &mut BinaryHeap<Neighbour<C>>,
+    ) {
+        if nodes.is_empty() || k == 0 {
+            return;
+        }
+        let mid = nodes.len() / 2;
+        let idx = nodes[mid];
+        let (point, goal) = (self.junctions[idx], self.junctions[target]);
+
+        if idx != target {
+            let candidate = Neighbour {
+                distance: self.metric.distance(point, goal),
+                junction: point,
+                idx,
+            };
+            if best.len() < k {
+                best.push(candidate);
+            } else if candidate < *best.peek().unwrap() {
+                best.pop();
+                best.push(candidate);
+            }
+        }
+
+        let (left, right) = (&nodes[..mid], &nodes[mid + 1..]);
+        let bound = self.metric.axis_bound(
+            depth % 3,
+            axis(&goal, depth % 3).diff(axis(&point, depth % 3)),
+        );
+        let (near, far) = if axis(&goal, depth % 3) < axis(&point, depth % 3) {
+            (left, right)
+        } else {
+            (right, left)
+        };
+        self.search(near, depth + 1, target, k, best);
+        // Equal distances must be visited too, ties are broken on the junction
+        if best.len() < k || bound <= best.peek().unwrap().distance {
+            self.search(far, depth + 1, target, k, best);
+        }
+    }
+}
+
+/// Junction found by a nearest neighbour query, ordered by distance then junction.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+struct Neighbour<C: Coordinate> {
+    distance: C::Distance,
+    junction: Junction<C>,
+    idx: usize,
+}
+
+/// Neighbours of one junction, fetched from the tree by doubling batches.
+#[derive(Debug)]
+struct NeighbourStream<C: Coordinate> {
+    batch: Vec<Neighbour<C>>,
+    consumed: usize,
+    k: usize,
+}
+
+impl<C: Coordinate> NeighbourStream<C> {
+    fn next(&mut self, tree: &KdTree<C>, idx: usize) -> Option<Neighbour<C>> {
+        if self.consumed == self.batch.len() {
+            // A short batch means every other junction has been seen
+            if self.batch.len() < self.k {
+                return None;
+            }
+            self.k *= 2;
+            self.batch = tree.nearest(idx, self.k);
+        }
+        let neighbour = self.batch.get(self.consumed).copied();
+        self.consumed += 1;
+        neighbour
+    }
+}
 
-    pairs.sort_by(|left, right| {
-        left.distance_sq
-            .cmp(&right.distance_sq)
-            .then_with(|| left.a.cmp(&right.a))
-            .then_with(|| left.b.cmp(&right.b))
-    });
+/// Sort key of a pair: distance, first junction, second junction.
+type PairKey<C> = (<C as Coordinate>::Distance, Junction<C>, Junction<C>);
+
+/// Junction pairs in increasing distance order (ties broken on the junctions),
+/// generated lazily by merging the neighbour stream of every junction.
+struct ClosestPairs<'a, C: Coordinate> {
+    junctions: &'a [Junction<C>],
+    tree: KdTree<'a, C>,
+    streams: Vec<NeighbourStream<C>>,
+    /// Next candidate of each stream, with the stream it came from.
+    heap: BinaryHeap<Reverse<(PairKey<C>, usize)>>,
+    last: Option<PairKey<C>>,
+}
+
+impl<'a, C: Coordinate> ClosestPairs<'a, C> {
+    fn new(junctions: &'a [Junction<C>], metric: &'a dyn Metric<C>) -> Self {
+        let mut pairs = ClosestPairs {
+            junctions,
+            tree: KdTree::new(junctions, metric),
+            streams: Vec::with_capacity(junctions.len()),
+            heap: BinaryHeap::new(),
+            last: None,
+        };
+        for idx in 0..junctions.len() {
+            let k = 4;
+            pairs.streams.push(NeighbourStream {
+                batch: pairs.tree.nearest(idx, k),
+                consumed: 0,
+                k,
+            });
+            pairs.advance(idx);
+        }
+        pairs
+    }
 
-    pairs
+    fn advance(&mut self, idx: usize) {
+        if let Some(neighbour) = self.streams[idx].next(&self.tree, idx) {
+            let a = self.junctions[idx].min(neighbour.junction);
+            let b = self.junctions[idx].max(neighbour.junction);
+            self.heap.push(Reverse(((neighbour.distance, a, b), idx)));
+        }
+    }
 }
 
-fn run(input: String) -> usize {
-    let (_, data) = parse(&input).unwrap();
-    let pairs = build_pairs(&data.junctions);
+impl<C: Coordinate> Iterator for ClosestPairs<'_, C> {
+    type Item = JunctionPair<C>;
 
-    let mut index_by_junction: HashMap<Junction, usize> = HashMap::new();
-    for (idx, junction) in data.junctions.iter().copied().enumerate() {
-        index_by_junction.insert(junction, idx);
+    fn next(&mut self) -> Option<JunctionPair<C>> {
+        // Every pair comes from the streams of both its junctions, skip the copy
+        while let Some(Reverse(((distance, a, b), idx))) = self.heap.pop() {
+            self.advance(idx);
+            if self.last == Some((distance, a, b)) {
+                continue;
+            }
+            self.last = Some((distance, a, b));
+            return Some(JunctionPair { a, b, distance });
+        }
+        None
     }
+}
+
+/// One step of single-linkage clustering: the two clusters joined by a tree edge.
+/// Clusters `0..n` are the junctions, merge `i` creates cluster `n + i`.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+struct Merge<C: Coordinate> {
+    left: usize,
+    right: usize,
+    distance: C::Distance,
+    height: f64,
+    size: usize,
+}
+
+/// Minimum spanning tree of the junctions and the matching dendrogram.
+#[derive(Debug, Serialize)]
+struct SpanningTree<C: Coordinate> {
+    junctions: Vec<Junction<C>>,
+    edges: Vec<JunctionPair<C>>,
+    merges: Vec<Merge<C>>,
+}
+
+impl<C: Coordinate> SpanningTree<C> {
+    /// Kruskal's algorithm over the lazily sorted pairs; stops once everything is connected.
+    fn kruskal(junctions: &[Junction<C>], metric: &dyn Metric<C>) -> Self {
+        let mut index_by_junction: HashMap<Junction<C>, usize> = HashMap::new();
+        for (idx, junction) in junctions.iter().copied().enumerate() {
+            index_by_junction.insert(junction, idx);
+        }
+
+        let mut dsu = DisjointSet::new(junctions.len());
+        // Dendrogram cluster currently represented by each set root
+        let mut cluster_of_root: Vec<usize> = (0..junctions.len()).collect();
+        let mut sizes = vec![1; junctions.len()];
+        let mut edges = Vec::new();
+        let mut merges = Vec::new();
+
+        for pair in ClosestPairs::new(junctions, metric) {
+            if edges.len() + 1 >= junctions.len() {
+                break;
+            }
+            let a_idx = *index_by_junction.get(&pair.a).expect("junction exists");
+            let b_idx = *index_by_junction.get(&pair.b).expect("junction exists");
+            let (root_a, root_b) = (dsu.find(a_idx), dsu.find(b_idx));
+
+            if dsu.union(a_idx, b_idx) {
+                let root = dsu.find(a_idx);
+                let size = sizes[root_a] + sizes[root_b];
+                merges.push(Merge {
+                    left: cluster_of_root[root_a],
+                    right: cluster_of_root[root_b],
+                    distance: pair.distance,
+                    height: metric.length(pair.distance),
+                    size,
+                });
+                cluster_of_root[root] = junctions.len() + merges.len() - 1;
+                sizes[root] = size;
+                edges.push(pair);
+            }
+        }
 
-    let mut dsu = DisjointSet::new(data.junctions.len());
-    let mut components_remaining = data.junctions.len();
+        SpanningTree {
+            junctions: junctions.to_vec(),
+            edges,
+            merges,
+        }
+    }
 
-    for pair in pairs {
-        let a_idx = *index_by_junction.get(&pair.a).expect("junction exists");
-        let b_idx = *index_by_junction.get(&pair.b).expect("junction exists");
+    /// The dendrogram in Newick format. Leaves are labelled with their coordinates
+    /// and branch lengths are differences in merge height.
+    fn to_newick(&self) -> String {
+        enum Visit {
+            Enter(usize),
+            Comma,
+            Close(usize),
+        }
 
-        if dsu.union(a_idx, b_idx) {
-            components_remaining -= 1;
-            if components_remaining == 1 {
-                return pair.a.x * pair.b.x;
+        let leaves = self.junctions.len();
+        if leaves == 0 {
+            return ";".to_string();
+        }
+        let height = |cluster: usize| {
+            cluster
+                .checked_sub(leaves)
+                .map_or(0.0, |merge| self.merges[merge].height)
+        };
+        let mut parent_height = vec![None; leaves + self.merges.len()];
+        for (idx, merge) in self.merges.iter().enumerate() {
+            let merged = height(leaves + idx);
+            parent_height[merge.left] = Some(merged);
+            parent_height[merge.right] = Some(merged);
+        }
+        let branch = |cluster: usize| match parent_height[cluster] {
+            Some(parent) => format!(":{}", parent - height(cluster)),
+            None => String::new(),
+        };
+
+        // Explicit stack: chain-like dendrograms are far too deep to recurse on
+        let mut newick = String::new();
+        let mut stack = vec![Visit::Enter(leaves + self.merges.len() - 1)];
+        while let Some(visit) = stack.pop() {
+            match visit {
+                Visit::Enter(cluster) if cluster < leaves => {
+                    let Junction { x, y, z } = self.junctions[cluster];
+                    newick.push_str(&format!("'{},{},{}'{}", x, y, z, branch(cluster)));
+                }
+                Visit::Enter(cluster) => {
+                    let merge = &self.merges[cluster - leaves];
+                    newick.push('(');
+                    stack.push(Visit::Close(cluster));
+                    stack.push(Visit::Enter(merge.right));
+                    stack.push(Visit::Comma);
+                    stack.push(Visit::Enter(merge.left));
+                }
+                Visit::Comma => newick.push(','),
+                Visit::Close(cluster) => {
+                    newick.push(')');
+                    newick.push_str(&branch(cluster));
+                }
             }
         }
+        newick.push(';');
+        newick
+    }
+}
+
+/// The last connection needed to join every junction into one circuit.
+fn last_connection<C: Coordinate>(
+    junctions: &[Junction<C>],
+    metric: &dyn Metric<C>,
+) -> JunctionPair<C> {
+    let tree = SpanningTree::kruskal(junctions, metric);
+
+    match tree.edges.last() {
+        Some(pair) if tree.edges.len() + 1 == junctions.len() => pair.clone(),
+        _ => panic!("failed to connect all junction boxes"),
+    }
+}
+
+fn run(input: String) -> i128 {
+    let data = read_junctions::<i64>(&input).unwrap_or_else(|error| report(error));
+    let pair = last_connection(&data.junctions, &EuclideanSquared);
+
+    i128::from(pair.a.x) * i128::from(pair.b.x)
+}
+
+/// Handles `--metric` and `--mst`; returns the last connection unless the tree was exported.
+fn explore<C: Coordinate>(input: &str, args: &[String]) -> Option<JunctionPair<C>> {
+    let data = read_junctions::<C>(input).unwrap_or_else(|error| report(error));
+    // `--metric euclidean|manhattan|chebyshev|weighted:x,y,z`
+    let metric: Box<dyn Metric<C>> = match args.iter().position(|arg| arg == "--metric") {
+        Some(pos) => args
+            .get(pos + 1)
+            .and_then(|name| parse_metric(name))
+            .expect("Invalid metric"),
+        None => Box::new(EuclideanSquared),
+    };
+    if let Some(pos) = args.iter().position(|arg| arg == "--mst") {
+        // `--mst [json|newick]`
+        let tree = SpanningTree::kruskal(&data.junctions, metric.as_ref());
+        match args.get(pos + 1).map(String::as_str) {
+            Some("newick") => println!("{}", tree.to_newick()),
+            _ => println!("{}", serde_json::to_string_pretty(&tree).unwrap()),
+        }
+        return None;
     }
 
-    panic!("failed to connect all junction boxes")
+    Some(last_connection(&data.junctions, metric.as_ref()))
 }
 
 fn main() {
     let input = read_input(None);
 
+    let args: Vec<String> = env::args().collect();
+    if args.iter().any(|arg| arg == "--float") {
+        // Fractional coordinates
+        if let Some(pair) = explore::<Float>(&input, &args) {
+            println!("Answer: {}", pair.a.x.0 * pair.b.x.0);
+        }
+        return;
+    }
+    if args.iter().any(|arg| arg == "--metric" || arg == "--mst") {
+        if let Some(pair) = explore::<i64>(&input, &args) {
+            println!("Answer: {}", i128::from(pair.a.x) * i128::from(pair.b.x));
+        }
+        return;
+    }
+
     let answer = run(input);
 
     println!("Answer: {}", answer);
@@ -178,6 +746,7 @@ mod tests {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
     use super::*;
     use indoc::indoc;
+    use itertools::Itertools;
     use pretty_assertions::{assert_eq, assert_ne};
 
     #[test]
@@ -214,4 +783,187 @@ mod tests {
         let answer = run(input);
         assert_eq!(answer, 25272);
     }
+
+    #[test]
+    fn test_spanning_tree() {
+        let junctions = vec![
+            Junction { x: 0, y: 0, z: 0 },
+            Junction { x: 3, y: 0, z: 0 },
+            Junction { x: 10, y: 0, z: 0 },
+            Junction { x: 3, y: 4, z: 0 },
+        ];
+        let tree = SpanningTree::kruskal(&junctions, &EuclideanSquared);
+
+        let distances: Vec<u128> = tree.edges.iter().map(|pair| pair.distance).collect();
+        assert_eq!(distances, vec![9, 16, 49]);
+        assert_eq!(
+            tree.merges,
+            vec![
+                Merge {
+                    left: 0,
+                    right: 1,
+                    distance: 9,
+                    height: 3.0,
+                    size: 2
+                },
+                Merge {
+                    left: 4,
+                    right: 3,
+                    distance: 16,
+                    height: 4.0,
+                    size: 3
+                },
+                Merge {
+                    left: 5,
+                    right: 2,
+                    distance: 49,
+                    height: 7.0,
+                    size: 4
+                },
+            ]
+        );
+        assert_eq!(
+            tree.to_newick(),
+            "((('0,0,0':3,'3,0,0':3):1,'3,4,0':4):3,'10,0,0':7);"
+        );
+
+        let json = serde_json::to_value(&tree).unwrap();
+        assert_eq!(
+            json["edges"][2]["b"],
+            serde_json::json!({"x": 10, "y": 0, "z": 0})
+        );
+        assert_eq!(json["merges"][2]["size"], 4);
+    }
+
+    #[test]
+    fn test_spanning_tree_manhattan() {
+        let junctions = vec![
+            Junction { x: 0, y: 0, z: 0 },
+            Junction { x: 3, y: 0, z: 0 },
+            Junction { x: 10, y: 0, z: 0 },
+            Junction { x: 3, y: 4, z: 0 },
+        ];
+        let tree = SpanningTree::kruskal(&junctions, parse_metric("manhattan").unwrap().as_ref());
+
+        let edges: Vec<(Junction<i64>, Junction<i64>, u128)> = tree
+            .edges
+            .iter()
+            .map(|pair| (pair.a, pair.b, pair.distance))
+            .collect();
+        // (0,0,0)-(3,4,0) ties with (3,0,0)-(10,0,0) at 7 and comes first, but closes a cycle
+        assert_eq!(
+            edges,
+            vec![
+                (junctions[0], junctions[1], 3),
+                (junctions[1], junctions[3], 4),
+                (junctions[1], junctions[2], 7),
+            ]
+        );
+        assert!(parse_metric::<i64>("weighted:1,2").is_none());
+    }
+
+    /// Total weight of a minimum spanning tree, by Prim's algorithm over every pair.
+    fn prim_weight(junctions: &[Junction<i64>], metric: &dyn Metric<i64>) -> u128 {
+        let mut best: Vec<Option<u128>> = vec![None; junctions.len()];
+        let mut in_tree = vec![false; junctions.len()];
+        best[0] = Some(0);
+        let mut total = 0;
+        while let Some(idx) = (0..junctions.len())
+            .filter(|idx| !in_tree[*idx] && best[*idx].is_some())
+            .min_by_key(|idx| best[*idx])
+        {
+            in_tree[idx] = true;
+            total += best[idx].unwrap();
+            for other in 0..junctions.len() {
+                let distance = metric.distance(junctions[idx], junctions[other]);
+                if !in_tree[other] && best[other].is_none_or(|best| distance < best) {
+                    best[other] = Some(distance);
+                }
+            }
+        }
+        total
+    }
+
+    #[test]
+    fn test_spanning_tree_matches_prim() {
+        // Small coordinates so that many pairs share a distance
+        let mut seed = 12345_usize;
+        let mut next = || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as i64 % 12 - 6
+        };
+        let junctions: Vec<Junction<i64>> = (0..150)
+            .map(|_| Junction {
+                x: next(),
+                y: next(),
+                z: next(),
+            })
+            .unique()
+            .collect();
+
+        for name in ["euclidean", "manhattan", "chebyshev", "weighted:1,3,2"] {
+            let metric = parse_metric(name).unwrap();
+            let tree = SpanningTree::kruskal(&junctions, metric.as_ref());
+            assert_eq!(tree.edges.len() + 1, junctions.len(), "{}", name);
+            assert_eq!(
+                tree.merges.last().unwrap().size,
+                junctions.len(),
+                "{}",
+                name
+            );
+            let weight: u128 = tree.edges.iter().map(|pair| pair.distance).sum();
+            assert_eq!(weight, prim_weight(&junctions, metric.as_ref()), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_signed_and_float_coordinates() {
+        let input = "-3,0,2\n4611686018427387903,-4611686018427387903,0\n-4611686018427387903,4611686018427387903,0";
+        let (_, data) = parse::<i64>(input).unwrap();
+        assert_eq!(data.junctions[0], Junction { x: -3, y: 0, z: 2 });
+        // Far beyond u64, but within u128
+        let limit = u128::from(COORDINATE_LIMIT - 1);
+        assert_eq!(
+            Metric::<i64>::distance(&EuclideanSquared, data.junctions[1], data.junctions[2]),
+            8 * limit * limit
+        );
+        assert!(<i64 as Coordinate>::parse("4611686018427387904").is_err());
+
+        // An out of range line is reported, not dropped with everything after it
+        assert_eq!(
+            read_junctions::<i64>("1,2,3\n4611686018427387904,0,0\n5,6,7")
+                .map(|data| data.junctions),
+            Err(InvalidJunction {
+                line: 2,
+                text: "4611686018427387904,0,0".to_string()
+            })
+        );
+        assert_eq!(
+            read_junctions::<i64>("1,2,3\n4,5,x\n")
+                .unwrap_err()
+                .to_string(),
+            "line 2: invalid junction 4,5,x"
+        );
+        assert_eq!(
+            read_junctions::<i64>("1,2,3\n4,5,6\n").map(|data| data.junctions.len()),
+            Ok(2)
+        );
+
+        let (_, data) = parse::<Float>("1.5,-2,0.25\n-0,0,0\n0,0,0").unwrap();
+        assert_eq!(
+            data.junctions[0],
+            Junction {
+                x: Float(1.5),
+                y: Float(-2.0),
+                z: Float(0.25)
+            }
+        );
+        // -0 sorts before 0 and stays a separate junction
+        assert!(data.junctions[1] < data.junctions[2]);
+        let pair = last_connection(&data.junctions, &EuclideanSquared);
+        assert_eq!(pair.a, data.junctions[1]);
+        assert_eq!(pair.distance, Float(1.5 * 1.5 + 2.0 * 2.0 + 0.25 * 0.25));
+    }
 }
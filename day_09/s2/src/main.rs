@@ -6,6 +6,8 @@ use nom::{
     multi::separated_list1,
     sequence::{pair, preceded, terminated},
 };
+use std::cmp::Ordering;
+use std::collections::HashMap;
 
 fn read_input(input: Option<&str>) -> String {
     let input = match input {
@@ -46,8 +48,8 @@ struct Points {
 
 #[derive(Clone, Copy, Debug)]
 struct Point {
-    x: f64,
-    y: f64,
+    x: i128,
+    y: i128,
 }
 
 #[allow(dead_code)]
@@ -55,22 +57,40 @@ struct Point {
 struct Rectangle {
     p1: Points,
     p2: Points,
-    surface: usize,
+    surface: u128,
 }
 
 fn to_point(p: &Points) -> Point {
     Point {
-        x: p.x as f64,
-        y: p.y as f64,
+        x: p.x as i128,
+        y: p.y as i128,
     }
 }
 
-fn cross(a: Point, b: Point, c: Point) -> f64 {
-    (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+/// Compares `a * b` with `c * d` exactly. The factors are differences of 64-bit
+/// coordinates, so each product fits in a `u128` magnitude but not in an `i128`.
+fn cmp_products(a: i128, b: i128, c: i128, d: i128) -> Ordering {
+    let signed = |x: i128, y: i128| {
+        let magnitude = x.unsigned_abs() * y.unsigned_abs();
+        let negative = (x < 0) != (y < 0) && magnitude != 0;
+        (negative, magnitude)
+    };
+
+    match (signed(a, b), signed(c, d)) {
+        ((false, left), (false, right)) => left.cmp(&right),
+        ((true, left), (true, right)) => right.cmp(&left),
+        ((true, _), (false, _)) => Ordering::Less,
+        ((false, _), (true, _)) => Ordering::Greater,
+    }
+}
+
+/// Sign of the cross product of `b - a` and `c - a`: `Greater` when `c` lies to the left of `a -> b`.
+fn cross(a: Point, b: Point, c: Point) -> Ordering {
+    cmp_products(b.x - a.x, c.y - a.y, b.y - a.y, c.x - a.x)
 }
 
 fn on_segment(a: Point, b: Point, p: Point) -> bool {
-    cross(a, b, p) == 0.0
+    cross(a, b, p) == Ordering::Equal
         && p.x >= a.x.min(b.x)
         && p.x <= a.x.max(b.x)
         && p.y >= a.y.min(b.y)
@@ -80,13 +100,24 @@ fn on_segment(a: Point, b: Point, p: Point) -> bool {
 fn point_in_polygon(point: Point, polygon: &[Point]) -> bool {
     let mut inside = false;
 
-    for (a, b) in polygon.iter().zip(polygon.iter().cycle().skip(1)).take(polygon.len()) {
+    for (a, b) in polygon
+        .iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .take(polygon.len())
+    {
         if on_segment(*a, *b, point) {
             return true;
         }
 
-        let intersects = ((a.y > point.y) != (b.y > point.y))
-            && (point.x < (b.x - a.x) * (point.y - a.y) / (b.y - a.y) + a.x);
+        // The edge crosses the ray to the right of `point` when `point` is on the
+        // left of an upward edge or on the right of a downward one.
+        let intersects = (a.y > point.y) != (b.y > point.y)
+            && cross(*a, *b, point)
+                == if b.y > a.y {
+                    Ordering::Greater
+                } else {
+                    Ordering::Less
+                };
 
         if intersects {
             inside = !inside;
@@ -97,15 +128,20 @@ fn point_in_polygon(point: Point, polygon: &[Point]) -> bool {
 }
 
 fn proper_intersection(a: Point, b: Point, c: Point, d: Point) -> bool {
-    let ab_c = cross(a, b, c);
-    let ab_d = cross(a, b, d);
-    let cd_a = cross(c, d, a);
-    let cd_b = cross(c, d, b);
+    let opposite = |left: Ordering, right: Ordering| {
+        left != Ordering::Equal && right != Ordering::Equal && left != right
+    };
 
-    ab_c * ab_d < 0.0 && cd_a * cd_b < 0.0
+    opposite(cross(a, b, c), cross(a, b, d)) && opposite(cross(c, d, a), cross(c, d, b))
 }
 
-fn rectangle_inside_polygon(x_min: f64, x_max: f64, y_min: f64, y_max: f64, polygon: &[Point]) -> bool {
+fn rectangle_inside_polygon(
+    x_min: i128,
+    x_max: i128,
+    y_min: i128,
+    y_max: i128,
+    polygon: &[Point],
+) -> bool {
     let corners = [
         Point { x: x_min, y: y_min },
         Point { x: x_max, y: y_min },
@@ -141,10 +177,109 @@ fn rectangle_inside_polygon(x_min: f64, x_max: f64, y_min: f64, y_max: f64, poly
     true
 }
 
-fn run(input: String) -> usize {
+/// Sorted distinct values, each with a compressed index of its own and with every
+/// non-empty gap between two consecutive values squashed into one more index.
+/// Returns the first value covered by each index and the index of every value.
+fn compress(values: impl Iterator<Item = usize>) -> (Vec<usize>, HashMap<usize, usize>) {
+    let values: Vec<usize> = values.sorted_unstable().dedup().collect();
+    let mut starts = Vec::new();
+    let mut index_of = HashMap::new();
+
+    for (idx, value) in values.iter().enumerate() {
+        index_of.insert(*value, starts.len());
+        starts.push(*value);
+        if values.get(idx + 1).is_some_and(|next| next - value > 1) {
+            starts.push(value + 1);
+        }
+    }
+
+    (starts, index_of)
+}
+
+/// The tiles of a rectilinear polygon rasterised on its compressed coordinates,
+/// with a 2-D prefix sum of the outside cells.
+struct CompressedGrid {
+    col_of_x: HashMap<usize, usize>,
+    row_of_y: HashMap<usize, usize>,
+    /// Outside cells above and to the left of each grid corner
+    outside: Vec<Vec<usize>>,
+}
+
+impl CompressedGrid {
+    /// `None` unless every edge of the polygon is horizontal or vertical.
+    fn new(points: &[Points]) -> Option<Self> {
+        let edges: Vec<(&Points, &Points)> = points
+            .iter()
+            .zip(points.iter().cycle().skip(1))
+            .take(points.len())
+            .collect();
+        if edges.iter().any(|(a, b)| a.x != b.x && a.y != b.y) {
+            return None;
+        }
+
+        let (xs, col_of_x) = compress(points.iter().map(|p| p.x));
+        let (ys, row_of_y) = compress(points.iter().map(|p| p.y));
+        let mut inside = vec![vec![false; xs.len()]; ys.len()];
+
+        // Boundary tiles are inside
+        for (a, b) in edges.iter() {
+            let (c1, c2) = (col_of_x[&a.x], col_of_x[&b.x]);
+            let (r1, r2) = (row_of_y[&a.y], row_of_y[&b.y]);
+            for row in inside.iter_mut().take(r1.max(r2) + 1).skip(r1.min(r2)) {
+                row[c1.min(c2)..=c1.max(c2)].fill(true);
+            }
+        }
+
+        // Any other tile is inside when an odd number of vertical edges cross its row to the left
+        for (row, y) in inside.iter_mut().zip(ys.iter()) {
+            let crossings: Vec<usize> = edges
+                .iter()
+                .filter(|(a, b)| a.x == b.x && (a.y > *y) != (b.y > *y))
+                .map(|(a, _)| a.x)
+                .sorted_unstable()
+                .collect();
+            let mut left = 0;
+            for (cell, x) in row.iter_mut().zip(xs.iter()) {
+                while crossings.get(left).is_some_and(|crossing| crossing < x) {
+                    left += 1;
+                }
+                *cell |= left % 2 == 1;
+            }
+        }
+
+        let mut outside = vec![vec![0; xs.len() + 1]; ys.len() + 1];
+        for r in 0..ys.len() {
+            for c in 0..xs.len() {
+                outside[r + 1][c + 1] = outside[r][c + 1] + outside[r + 1][c] - outside[r][c]
+                    + usize::from(!inside[r][c]);
+            }
+        }
+
+        Some(CompressedGrid {
+            col_of_x,
+            row_of_y,
+            outside,
+        })
+    }
+
+    /// Whether every tile of the rectangle spanned by two vertices is inside, in O(1).
+    fn rectangle_inside(&self, p1: &Points, p2: &Points) -> bool {
+        let (c1, c2) = (self.col_of_x[&p1.x], self.col_of_x[&p2.x]);
+        let (r1, r2) = (self.row_of_y[&p1.y], self.row_of_y[&p2.y]);
+        let (c_min, c_max) = (c1.min(c2), c1.max(c2) + 1);
+        let (r_min, r_max) = (r1.min(r2), r1.max(r2) + 1);
+
+        self.outside[r_max][c_max] + self.outside[r_min][c_min]
+            == self.outside[r_min][c_max] + self.outside[r_max][c_min]
+    }
+}
+
+fn run(input: String) -> u128 {
     let (_, data) = parse(&input).unwrap();
 
     let polygon: Vec<Point> = data.points.iter().map(to_point).collect();
+    // Rectilinear polygons get O(1) rectangle checks, anything else the exact geometric test
+    let grid = CompressedGrid::new(&data.points);
 
     let rectangles: Vec<Rectangle> = data
         .points
@@ -158,16 +293,22 @@ fn run(input: String) -> usize {
                 return None;
             }
 
-            let x_min = p1.x.min(p2.x) as f64;
-            let x_max = p1.x.max(p2.x) as f64;
-            let y_min = p1.y.min(p2.y) as f64;
-            let y_max = p1.y.max(p2.y) as f64;
-
-            if !rectangle_inside_polygon(x_min, x_max, y_min, y_max, &polygon) {
+            let inside = match &grid {
+                Some(grid) => grid.rectangle_inside(p1, p2),
+                None => {
+                    let x_min = p1.x.min(p2.x) as i128;
+                    let x_max = p1.x.max(p2.x) as i128;
+                    let y_min = p1.y.min(p2.y) as i128;
+                    let y_max = p1.y.max(p2.y) as i128;
+
+                    rectangle_inside_polygon(x_min, x_max, y_min, y_max, &polygon)
+                }
+            };
+            if !inside {
                 return None;
             }
 
-            let surface = (p1.x.abs_diff(p2.x) + 1) * (p1.y.abs_diff(p2.y) + 1);
+            let surface = (p1.x.abs_diff(p2.x) as u128 + 1) * (p1.y.abs_diff(p2.y) as u128 + 1);
             Some(Rectangle {
                 p1: p1.clone(),
                 p2: p2.clone(),
@@ -222,4 +363,81 @@ mod tests {
         let answer = run(input);
         assert_eq!(answer, 24);
     }
+
+    #[test]
+    fn test_run_large_coordinates() {
+        // The example shifted far beyond f64 precision and close to the top of u64
+        let offset: u64 = u64::MAX - 20;
+        let input: String = [
+            (7, 1),
+            (11, 1),
+            (11, 7),
+            (9, 7),
+            (9, 5),
+            (2, 5),
+            (2, 3),
+            (7, 3),
+        ]
+        .iter()
+        .map(|(x, y)| format!("{},{}\n", offset + x, offset + y))
+        .collect();
+        assert_eq!(run(input), 24);
+
+        // Scaled up: the surface no longer fits in 64 bits
+        let scale: u64 = 1 << 60;
+        let input: String = [
+            (7, 1),
+            (11, 1),
+            (11, 7),
+            (9, 7),
+            (9, 5),
+            (2, 5),
+            (2, 3),
+            (7, 3),
+        ]
+        .iter()
+        .map(|(x, y)| format!("{},{}\n", scale * x, scale * y))
+        .collect();
+        assert_eq!(
+            run(input),
+            (7 * scale as u128 + 1) * (2 * scale as u128 + 1)
+        );
+    }
+
+    #[test]
+    fn test_compressed_grid() {
+        // A U shape: the notch between the arms is outside
+        let (_, data) = parse("0,0\n10,0\n10,10\n7,10\n7,3\n3,3\n3,10\n0,10").unwrap();
+        let grid = CompressedGrid::new(&data.points).unwrap();
+        let point = |x, y| Points { x, y };
+
+        assert!(grid.rectangle_inside(&point(0, 0), &point(10, 3)));
+        assert!(grid.rectangle_inside(&point(7, 10), &point(10, 0)));
+        assert!(!grid.rectangle_inside(&point(0, 0), &point(10, 10)));
+        assert!(!grid.rectangle_inside(&point(3, 3), &point(7, 10)));
+
+        let (_, data) = parse("0,0\n4,0\n2,3").unwrap();
+        assert!(CompressedGrid::new(&data.points).is_none());
+    }
+
+    #[test]
+    fn test_cross_exact() {
+        let point = |x: u64, y: u64| {
+            to_point(&Points {
+                x: x as usize,
+                y: y as usize,
+            })
+        };
+        let (a, b) = (point(0, 0), point(u64::MAX, u64::MAX - 1));
+        // Collinear in f64, one unit off the line in exact arithmetic
+        assert_eq!(
+            cross(a, b, point(u64::MAX - 1, u64::MAX - 2)),
+            Ordering::Less
+        );
+        assert_eq!(cross(a, b, point(u64::MAX, u64::MAX - 1)), Ordering::Equal);
+        assert_eq!(
+            cmp_products(-(1 << 64) + 1, (1 << 64) - 1, 0, 5),
+            Ordering::Less
+        );
+    }
 }